@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS model_aliases (
+  id SERIAL PRIMARY KEY,
+  provider TEXT NOT NULL,
+  alias TEXT NOT NULL,
+  model TEXT NOT NULL,
+  rewrite_response BOOLEAN NOT NULL DEFAULT TRUE,
+  UNIQUE (provider, alias)
+);
@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS clients (
+  id SERIAL PRIMARY KEY,
+  name TEXT NOT NULL UNIQUE,
+  secret TEXT NOT NULL UNIQUE
+);
+
+-- `provider` and `client` scope the rule, NULL matches any
+CREATE TABLE IF NOT EXISTS model_rules (
+  id SERIAL PRIMARY KEY,
+  provider TEXT,
+  client TEXT,
+  pattern TEXT NOT NULL,
+  allow BOOLEAN NOT NULL
+);
@@ -0,0 +1,9 @@
+-- `provider`, `model` (pattern) and `client` scope the rule, NULL matches any
+CREATE TABLE IF NOT EXISTS prompt_rules (
+  id SERIAL PRIMARY KEY,
+  provider TEXT,
+  model TEXT,
+  client TEXT,
+  action TEXT NOT NULL CHECK (action IN ('prepend_system', 'append_system', 'wrap_user')),
+  content TEXT NOT NULL
+);
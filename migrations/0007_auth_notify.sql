@@ -0,0 +1,26 @@
+-- Notifies listeners on `auth_changed` when keys are added, removed or reconfigured.
+-- Counter and state columns (sent, valid, used_at, cooldown) are written by the proxy itself
+-- and do not notify, so replicas syncing do not notify each other in a loop.
+CREATE OR REPLACE FUNCTION notify_auth_changed() RETURNS TRIGGER AS $$
+BEGIN
+  PERFORM pg_notify('auth_changed', TG_OP);
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS auth_changed_insert_delete ON auth;
+CREATE TRIGGER auth_changed_insert_delete
+  AFTER INSERT OR DELETE ON auth
+  FOR EACH STATEMENT EXECUTE FUNCTION notify_auth_changed();
+
+DROP TRIGGER IF EXISTS auth_changed_update ON auth;
+CREATE TRIGGER auth_changed_update
+  AFTER UPDATE ON auth
+  FOR EACH ROW
+  WHEN (
+    OLD.provider IS DISTINCT FROM NEW.provider
+    OR OLD.api_key IS DISTINCT FROM NEW.api_key
+    OR OLD.max IS DISTINCT FROM NEW.max
+    OR OLD.comments IS DISTINCT FROM NEW.comments
+  )
+  EXECUTE FUNCTION notify_auth_changed();
@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS auth (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  provider TEXT NOT NULL,
+  api_key TEXT NOT NULL UNIQUE,
+  sent INTEGER NOT NULL DEFAULT 0,
+  max INTEGER NOT NULL DEFAULT 0,
+  valid BOOLEAN NOT NULL DEFAULT TRUE,
+  -- RFC 3339, as written by the proxy, so timestamps compare as text
+  used_at TEXT NOT NULL DEFAULT '1970-01-01T00:00:00+00:00',
+  cooldown BOOLEAN NOT NULL DEFAULT FALSE,
+  comments TEXT
+);
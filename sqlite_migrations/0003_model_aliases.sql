@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS model_aliases (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  provider TEXT NOT NULL,
+  alias TEXT NOT NULL,
+  model TEXT NOT NULL,
+  rewrite_response BOOLEAN NOT NULL DEFAULT TRUE,
+  UNIQUE (provider, alias)
+);
@@ -1,38 +1,62 @@
-use crate::{env::Env, providers::Provider, proxy::webshare::Proxy};
+use crate::{
+    cache::ResponseCache,
+    coalesce::Flight,
+    db::{model_access::ModelRule, model_alias::ModelAlias, prompt_rule::PromptRule, Storage},
+    env::Env,
+    providers::Provider,
+    proxy::webshare::Proxy,
+};
+use lru::LruCache;
 use rand::rngs::SmallRng;
 use rand::SeedableRng;
-use sqlx::PgPool;
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashMap, num::NonZeroUsize, sync::Arc};
 use tokio::{sync::Mutex, time::Instant};
 
 pub struct AppState {
-    pub pool: PgPool,
+    pub storage: Storage,
     pub env: Env,
     pub rng: Arc<Mutex<SmallRng>>,
     pub proxies: Arc<Mutex<Vec<Arc<Proxy>>>>,
     pub proxies_last_synced_at: Arc<Mutex<Instant>>,
     pub providers: Arc<Mutex<HashMap<String, Arc<Provider>>>>,
     pub show_chat: Arc<Mutex<bool>>,
+    /// Model aliases by provider and alias
+    pub model_aliases: Arc<Mutex<HashMap<(String, String), ModelAlias>>>,
+    /// Client names by secret
+    pub clients: Arc<Mutex<HashMap<String, String>>>,
+    pub model_rules: Arc<Mutex<Vec<ModelRule>>>,
+    pub prompt_rules: Arc<Mutex<Vec<PromptRule>>>,
+    /// `None` if `CACHE_CAPACITY` is not set
+    pub response_cache: Option<Arc<Mutex<ResponseCache>>>,
+    /// In-flight chat completions by request hash, see `COALESCE_REQUESTS`
+    pub inflight: Arc<Mutex<HashMap<String, Arc<Flight>>>>,
 }
 
 impl AppState {
     pub async fn new() -> Self {
         let env = Env::new();
 
-        let pool = PgPool::connect(&env.database_url).await.unwrap();
-        sqlx::migrate!("./migrations")
-            .run(&pool)
+        let storage = Storage::connect(&env.database_url)
             .await
-            .expect("failed to run migrations");
+            .expect("failed to connect to the database");
+
+        let response_cache = NonZeroUsize::new(env.cache_capacity)
+            .map(|capacity| Arc::new(Mutex::new(LruCache::new(capacity))));
 
         Self {
-            pool,
+            storage,
             env,
             rng: Arc::new(Mutex::new(SmallRng::from_os_rng())),
             proxies: Arc::new(Mutex::new(vec![])),
             proxies_last_synced_at: Arc::new(Mutex::new(tokio::time::Instant::now())),
             providers: Arc::new(Mutex::new(HashMap::new())),
             show_chat: Arc::new(Mutex::new(true)),
+            model_aliases: Arc::new(Mutex::new(HashMap::new())),
+            clients: Arc::new(Mutex::new(HashMap::new())),
+            model_rules: Arc::new(Mutex::new(vec![])),
+            prompt_rules: Arc::new(Mutex::new(vec![])),
+            response_cache,
+            inflight: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 
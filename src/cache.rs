@@ -0,0 +1,238 @@
+use crate::{
+    app_state::AppState,
+    db::StorageFn as _,
+    utils::{aggregate::ChatAggregator, sse::is_event_stream},
+};
+use axum::{
+    body::{Body, Bytes},
+    http::{header, HeaderMap, HeaderValue, Response, StatusCode},
+    response::IntoResponse,
+};
+use chrono::{DateTime, TimeDelta, Utc};
+use futures::StreamExt as _;
+use lru::LruCache;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::{sync::Arc, time::Duration};
+
+/// Request header opting in to the cache, and response header telling `hit` or `miss`
+pub const CACHE_HEADER: &str = "x-lift-cache";
+
+/// Cached chat completions by key, with when they were created
+pub type ResponseCache = LruCache<String, (DateTime<Utc>, Bytes)>;
+
+/// A cacheable chat completions request.
+pub struct CacheKey {
+    pub key: String,
+    pub provider: String,
+    pub model: String,
+}
+
+impl CacheKey {
+    /// Keys a request on the provider and a hash of its canonical body (sorted keys, without
+    /// stream options), if the cache is enabled and the request is deterministic (`temperature`
+    /// is 0) or opted in with the cache header.
+    pub fn new(
+        app: &Arc<AppState>,
+        provider_name: &str,
+        headers: &HeaderMap,
+        body: &Bytes,
+    ) -> Option<Self> {
+        app.response_cache.as_ref()?;
+        let Ok(Value::Object(mut body)) = serde_json::from_slice::<Value>(body) else {
+            return None;
+        };
+        let requested = headers
+            .get(CACHE_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| !matches!(value, "false" | "0"));
+        let deterministic = body.get("temperature").and_then(Value::as_f64) == Some(0.0);
+        if !requested && !deterministic {
+            return None;
+        }
+
+        body.remove("stream");
+        body.remove("stream_options");
+        let model = body
+            .get("model")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_owned();
+        Some(Self {
+            key: request_hash(provider_name, &Value::Object(body)),
+            provider: provider_name.to_owned(),
+            model,
+        })
+    }
+}
+
+/// Hashes a request on the provider and its canonical JSON body, whose keys are sorted.
+pub fn request_hash(provider_name: &str, body: &Value) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(provider_name.as_bytes());
+    hasher.update(b"\n");
+    hasher.update(body.to_string().as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+/// The creation time of the oldest cached responses still served, see `CACHE_MAX_AGE`.
+fn oldest_served(app: &Arc<AppState>) -> DateTime<Utc> {
+    app.env
+        .cache_max_age
+        .and_then(|max_age| TimeDelta::from_std(max_age).ok())
+        .and_then(|max_age| Utc::now().checked_sub_signed(max_age))
+        .unwrap_or(DateTime::UNIX_EPOCH)
+}
+
+/// Looks up a cached chat completion in memory, then in the database if persisted.
+/// Responses older than `CACHE_MAX_AGE` are not served.
+pub async fn get_cached_response(app: &Arc<AppState>, key: &CacheKey) -> Option<Bytes> {
+    let cache = app.response_cache.as_ref()?;
+    let since = oldest_served(app);
+    {
+        let mut cache = cache.lock().await;
+        match cache.get(&key.key) {
+            Some((created_at, response)) if *created_at >= since => return Some(response.clone()),
+            Some(_) => {
+                cache.pop(&key.key);
+            }
+            None => {}
+        }
+    }
+    if !app.env.cache_persist {
+        return None;
+    }
+    match app.storage.get_cached_response(&key.key, since).await {
+        Ok(Some(cached)) => {
+            let response = Bytes::from(cached.response);
+            cache
+                .lock()
+                .await
+                .put(key.key.clone(), (cached.created_at, response.clone()));
+            Some(response)
+        }
+        Ok(None) => None,
+        Err(e) => {
+            tracing::warn!("Error loading cached response: {}", e);
+            None
+        }
+    }
+}
+
+fn save_cached_response(app: &Arc<AppState>, key: &CacheKey, response: Bytes) {
+    let Some(cache) = app.response_cache.clone() else {
+        return;
+    };
+    let app = app.clone();
+    let (key, provider, model) = (key.key.clone(), key.provider.clone(), key.model.clone());
+    tokio::spawn(async move {
+        cache
+            .lock()
+            .await
+            .put(key.clone(), (Utc::now(), response.clone()));
+        if app.env.cache_persist {
+            let response = String::from_utf8_lossy(&response);
+            if let Err(e) = app
+                .storage
+                .save_cached_response(&key, &provider, &model, &response)
+                .await
+            {
+                tracing::warn!("Error saving cached response: {}", e);
+            }
+        }
+    });
+}
+
+/// Interval of deleting persisted responses older than `CACHE_MAX_AGE`
+const CACHE_PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Deletes the persisted responses older than `CACHE_MAX_AGE` at startup,
+/// then every `CACHE_PRUNE_INTERVAL`.
+pub fn spawn_cache_prune(app: &Arc<AppState>) {
+    if app.response_cache.is_none() || !app.env.cache_persist || app.env.cache_max_age.is_none() {
+        return;
+    }
+    let app = app.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(CACHE_PRUNE_INTERVAL);
+        loop {
+            interval.tick().await;
+            match app
+                .storage
+                .prune_cached_responses(oldest_served(&app))
+                .await
+            {
+                Ok(0) => {}
+                Ok(count) => tracing::info!("[Cache] Pruned {} expired responses", count),
+                Err(e) => tracing::error!("[Cache] Failed to prune expired responses: {}", e),
+            }
+        }
+    });
+}
+
+/// A chat completion response served from the cache.
+pub fn cached_response(response: Bytes) -> Response<Body> {
+    (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        response,
+    )
+        .into_response()
+}
+
+/// Caches a successful chat completion response once it is complete,
+/// streamed responses are aggregated while they are passed through.
+pub async fn cache_response(
+    app: &Arc<AppState>,
+    key: CacheKey,
+    res: Response<Body>,
+) -> Response<Body> {
+    if !res.status().is_success() {
+        return res;
+    }
+
+    let app = app.clone();
+    let (parts, body) = res.into_parts();
+    if is_event_stream(&parts.headers) {
+        let mut aggregator = ChatAggregator::new();
+        let stream = body
+            .into_data_stream()
+            .map(Some)
+            .chain(futures::stream::once(async { None }))
+            .filter_map(move |chunk| {
+                let chunk = match chunk {
+                    Some(Ok(chunk)) => {
+                        aggregator.push(&chunk);
+                        Some(Ok(chunk))
+                    }
+                    Some(Err(e)) => Some(Err(e)),
+                    None => {
+                        // only complete streams finish into a response
+                        let aggregator = std::mem::take(&mut aggregator);
+                        if let Ok(response) = aggregator.finish() {
+                            save_cached_response(&app, &key, Bytes::from(response.to_string()));
+                        }
+                        None
+                    }
+                };
+                async move { chunk }
+            });
+        return Response::from_parts(parts, Body::from_stream(stream));
+    }
+
+    let body = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(e) => return (StatusCode::BAD_GATEWAY, e.to_string()).into_response(),
+    };
+    if serde_json::from_slice::<Value>(&body).is_ok() {
+        save_cached_response(&app, &key, body.clone());
+    }
+    Response::from_parts(parts, Body::from(body))
+}
+
+/// Tells the client whether the response came from the cache.
+pub fn set_cache_status(res: &mut Response<Body>, hit: bool) {
+    let status = if hit { "hit" } else { "miss" };
+    res.headers_mut()
+        .insert(CACHE_HEADER, HeaderValue::from_static(status));
+}
@@ -0,0 +1,137 @@
+use crate::app_state::AppState;
+use axum::{
+    body::{Body, Bytes},
+    http::{HeaderMap, Response, StatusCode},
+    response::IntoResponse,
+};
+use futures::{Future, StreamExt as _};
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+
+#[derive(Default)]
+struct FlightState {
+    head: Option<(StatusCode, HeaderMap)>,
+    chunks: Vec<Bytes>,
+    error: Option<String>,
+    done: bool,
+}
+
+/// An upstream call shared by concurrent identical requests.
+/// The response is buffered as it arrives, so late joiners replay it from the start.
+pub struct Flight {
+    state: Mutex<FlightState>,
+    progress: watch::Receiver<()>,
+}
+
+/// The first of concurrent identical requests, making the upstream call.
+pub struct FlightLeader {
+    app: Arc<AppState>,
+    key: String,
+    flight: Arc<Flight>,
+    progress: watch::Sender<()>,
+}
+
+pub enum FlightRole {
+    Leader(FlightLeader),
+    Follower(Arc<Flight>),
+}
+
+/// Joins the in-flight call of a request, or starts one.
+pub async fn join_flight(app: &Arc<AppState>, key: String) -> FlightRole {
+    let mut inflight = app.inflight.lock().await;
+    if let Some(flight) = inflight.get(&key) {
+        return FlightRole::Follower(flight.clone());
+    }
+    let (progress, receiver) = watch::channel(());
+    let flight = Arc::new(Flight {
+        state: Mutex::new(FlightState::default()),
+        progress: receiver,
+    });
+    inflight.insert(key.clone(), flight.clone());
+    FlightRole::Leader(FlightLeader {
+        app: app.clone(),
+        key,
+        flight,
+        progress,
+    })
+}
+
+impl FlightLeader {
+    /// Makes the upstream call in the background, so it completes for the followers
+    /// even if the leading client goes away, and responds like a follower.
+    pub async fn lead<F>(self, upstream: F) -> Response<Body>
+    where
+        F: Future<Output = Response<Body>> + Send + 'static,
+    {
+        let flight = self.flight.clone();
+        tokio::spawn(async move {
+            let (parts, body) = upstream.await.into_parts();
+            self.flight.state.lock().unwrap().head = Some((parts.status, parts.headers));
+            self.progress.send_replace(());
+
+            let mut stream = body.into_data_stream();
+            while let Some(chunk) = stream.next().await {
+                match chunk {
+                    Ok(chunk) => self.flight.state.lock().unwrap().chunks.push(chunk),
+                    Err(e) => {
+                        self.flight.state.lock().unwrap().error = Some(e.to_string());
+                        break;
+                    }
+                }
+                self.progress.send_replace(());
+            }
+
+            // later requests start a new call
+            self.app.inflight.lock().await.remove(&self.key);
+            self.flight.state.lock().unwrap().done = true;
+            self.progress.send_replace(());
+        });
+        follow_flight(flight).await
+    }
+}
+
+/// Responds with the response of an in-flight call, as it arrives.
+pub async fn follow_flight(flight: Arc<Flight>) -> Response<Body> {
+    let mut progress = flight.progress.clone();
+    let (status, headers) = loop {
+        if let Some(head) = flight.state.lock().unwrap().head.clone() {
+            break head;
+        }
+        if progress.changed().await.is_err() {
+            let msg = "Coalesced request failed";
+            tracing::warn!(msg);
+            return (StatusCode::BAD_GATEWAY, msg).into_response();
+        }
+    };
+
+    let stream = futures::stream::unfold(
+        (flight, progress, 0, false),
+        |(flight, mut progress, index, ended)| async move {
+            if ended {
+                return None;
+            }
+            loop {
+                {
+                    let state = flight.state.lock().unwrap();
+                    if let Some(chunk) = state.chunks.get(index).cloned() {
+                        drop(state);
+                        return Some((Ok(chunk), (flight, progress, index + 1, false)));
+                    }
+                    if state.done {
+                        let error = state.error.clone()?;
+                        drop(state);
+                        let error = std::io::Error::other(error);
+                        return Some((Err(error), (flight, progress, index, true)));
+                    }
+                }
+                // the leader went away without finishing
+                progress.changed().await.ok()?;
+            }
+        },
+    );
+
+    let mut res = Response::new(Body::from_stream(stream));
+    *res.status_mut() = status;
+    *res.headers_mut() = headers;
+    res
+}
@@ -1,2 +1,145 @@
 pub mod auth;
+pub mod model_access;
+pub mod model_alias;
+pub mod postgres;
+pub mod prompt_rule;
 pub mod proxy;
+pub mod response_cache;
+pub mod sqlite;
+
+use crate::proxy::webshare::Proxy;
+use auth::{AuthState, ProviderAuth};
+use chrono::{DateTime, Utc};
+use eyre::Result;
+use model_access::{DbClient, ModelRule};
+use model_alias::ModelAlias;
+use postgres::PgStorage;
+use prompt_rule::PromptRule;
+use response_cache::CachedResponse;
+use sqlite::SqliteStorage;
+use std::sync::Arc;
+
+/// Database operations, implemented for each supported database.
+pub trait StorageFn {
+    /// Fetches all authentication records.
+    async fn get_all_auth(&self) -> Result<Vec<ProviderAuth>>;
+    /// Writes the unsynced changes of authentication records based on their IDs.
+    /// Counters are incremented and state is only set where changed, so replicas sharing the
+    /// database do not overwrite each other, and the resulting state of each record is returned.
+    async fn update_auth(&self, provider_auths: &[ProviderAuth]) -> Result<Vec<AuthState>>;
+    /// Leases a valid auth key of a provider, not on cooldown and with quota left, other than
+    /// `except`, charging it 1 so replicas sharing the database cannot spend the same quota.
+    async fn lease_auth(&self, provider: &str, except: Option<i32>) -> Result<Option<AuthState>>;
+    async fn reset_auth(&self, provider: &str) -> Result<u64>;
+    async fn save_proxies(&self, proxies: &[Arc<Proxy>]) -> Result<()>;
+    async fn load_proxies(&self) -> Result<Vec<Arc<Proxy>>>;
+    async fn load_clients(&self) -> Result<Vec<DbClient>>;
+    async fn load_model_rules(&self) -> Result<Vec<ModelRule>>;
+    async fn load_model_aliases(&self) -> Result<Vec<ModelAlias>>;
+    /// Loads the prompt rules in the order they are applied.
+    async fn load_prompt_rules(&self) -> Result<Vec<PromptRule>>;
+    /// Fetches a cached response saved at or after `since`.
+    async fn get_cached_response(
+        &self,
+        key: &str,
+        since: DateTime<Utc>,
+    ) -> Result<Option<CachedResponse>>;
+    async fn save_cached_response(
+        &self,
+        key: &str,
+        provider: &str,
+        model: &str,
+        response: &str,
+    ) -> Result<()>;
+    /// Deletes the cached responses saved before `before`, returning how many.
+    async fn prune_cached_responses(&self, before: DateTime<Utc>) -> Result<u64>;
+}
+
+pub enum Storage {
+    Postgres(PgStorage),
+    Sqlite(SqliteStorage),
+}
+
+impl Storage {
+    /// Connects to the database of `DATABASE_URL` and runs its migrations,
+    /// SQLite for `sqlite:` URLs, Postgres otherwise.
+    pub async fn connect(database_url: &str) -> Result<Self> {
+        match database_url.starts_with("sqlite:") {
+            true => Ok(Self::Sqlite(SqliteStorage::connect(database_url).await?)),
+            false => Ok(Self::Postgres(PgStorage::connect(database_url).await?)),
+        }
+    }
+}
+
+macro_rules! dispatch {
+    ($self:ident, $storage:ident => $call:expr) => {
+        match $self {
+            Storage::Postgres($storage) => $call,
+            Storage::Sqlite($storage) => $call,
+        }
+    };
+}
+
+impl StorageFn for Storage {
+    async fn get_all_auth(&self) -> Result<Vec<ProviderAuth>> {
+        dispatch!(self, s => s.get_all_auth().await)
+    }
+
+    async fn update_auth(&self, provider_auths: &[ProviderAuth]) -> Result<Vec<AuthState>> {
+        dispatch!(self, s => s.update_auth(provider_auths).await)
+    }
+
+    async fn lease_auth(&self, provider: &str, except: Option<i32>) -> Result<Option<AuthState>> {
+        dispatch!(self, s => s.lease_auth(provider, except).await)
+    }
+
+    async fn reset_auth(&self, provider: &str) -> Result<u64> {
+        dispatch!(self, s => s.reset_auth(provider).await)
+    }
+
+    async fn save_proxies(&self, proxies: &[Arc<Proxy>]) -> Result<()> {
+        dispatch!(self, s => s.save_proxies(proxies).await)
+    }
+
+    async fn load_proxies(&self) -> Result<Vec<Arc<Proxy>>> {
+        dispatch!(self, s => s.load_proxies().await)
+    }
+
+    async fn load_clients(&self) -> Result<Vec<DbClient>> {
+        dispatch!(self, s => s.load_clients().await)
+    }
+
+    async fn load_model_rules(&self) -> Result<Vec<ModelRule>> {
+        dispatch!(self, s => s.load_model_rules().await)
+    }
+
+    async fn load_model_aliases(&self) -> Result<Vec<ModelAlias>> {
+        dispatch!(self, s => s.load_model_aliases().await)
+    }
+
+    async fn load_prompt_rules(&self) -> Result<Vec<PromptRule>> {
+        dispatch!(self, s => s.load_prompt_rules().await)
+    }
+
+    async fn get_cached_response(
+        &self,
+        key: &str,
+        since: DateTime<Utc>,
+    ) -> Result<Option<CachedResponse>> {
+        dispatch!(self, s => s.get_cached_response(key, since).await)
+    }
+
+    async fn save_cached_response(
+        &self,
+        key: &str,
+        provider: &str,
+        model: &str,
+        response: &str,
+    ) -> Result<()> {
+        dispatch!(self, s => s.save_cached_response(key, provider, model, response).await)
+    }
+
+    async fn prune_cached_responses(&self, before: DateTime<Utc>) -> Result<u64> {
+        dispatch!(self, s => s.prune_cached_responses(before).await)
+    }
+}
@@ -0,0 +1,17 @@
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct DbClient {
+    pub name: String,
+    pub secret: String,
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct ModelRule {
+    /// `None` applies to all providers
+    pub provider: Option<String>,
+    /// `None` applies to all clients
+    pub client: Option<String>,
+    /// Model id pattern, `*` matches any characters
+    pub pattern: String,
+    /// Whether matching models are allowed or denied
+    pub allow: bool,
+}
@@ -0,0 +1,8 @@
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct ModelAlias {
+    pub provider: String,
+    pub alias: String,
+    pub model: String,
+    /// Whether the `model` of responses is rewritten back to the alias
+    pub rewrite_response: bool,
+}
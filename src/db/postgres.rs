@@ -0,0 +1,230 @@
+use crate::{
+    db::{
+        auth::{AuthState, ProviderAuth},
+        model_access::{DbClient, ModelRule},
+        model_alias::ModelAlias,
+        prompt_rule::PromptRule,
+        proxy::DbProxy,
+        response_cache::CachedResponse,
+        StorageFn,
+    },
+    proxy::webshare::Proxy,
+};
+use chrono::{DateTime, Utc};
+use eyre::Result;
+use sqlx::{postgres::PgListener, PgPool};
+use std::sync::Arc;
+
+/// Channel notified by the `auth` table triggers
+const AUTH_CHANNEL: &str = "auth_changed";
+
+pub struct PgStorage {
+    pool: PgPool,
+}
+
+impl PgStorage {
+    pub async fn connect(database_url: &str) -> Result<Self> {
+        let pool = PgPool::connect(database_url).await?;
+        sqlx::migrate!("./migrations").run(&pool).await?;
+        Ok(Self { pool })
+    }
+
+    /// Listens for auth records added, removed or reconfigured, by any replica or by hand.
+    pub async fn listen_auth(&self) -> Result<PgListener> {
+        let mut listener = PgListener::connect_with(&self.pool).await?;
+        listener.listen(AUTH_CHANNEL).await?;
+        Ok(listener)
+    }
+}
+
+impl StorageFn for PgStorage {
+    async fn get_all_auth(&self) -> Result<Vec<ProviderAuth>> {
+        let all_auth: Vec<ProviderAuth> = sqlx::query_as("SELECT * FROM auth")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(all_auth)
+    }
+
+    async fn update_auth(&self, provider_auths: &[ProviderAuth]) -> Result<Vec<AuthState>> {
+        let query = r#"
+            UPDATE auth
+            SET sent = auth.sent + u.sent,
+                valid = COALESCE(u.valid, auth.valid),
+                used_at = GREATEST(auth.used_at, u.used_at),
+                cooldown = COALESCE(u.cooldown, auth.cooldown)
+            FROM UNNEST($1::int[], $2::int[], $3::bool[], $4::timestamptz[], $5::bool[])
+            AS u(id, sent, valid, used_at, cooldown)
+            WHERE auth.id = u.id
+            RETURNING auth.id, auth.sent, auth.valid, auth.used_at, auth.cooldown
+        "#;
+
+        let mut ids = Vec::with_capacity(provider_auths.len());
+        let mut sents = Vec::with_capacity(provider_auths.len());
+        let mut valids = Vec::with_capacity(provider_auths.len());
+        let mut used_ats = Vec::with_capacity(provider_auths.len());
+        let mut cooldowns = Vec::with_capacity(provider_auths.len());
+        for pa in provider_auths {
+            ids.push(pa.id);
+            sents.push(pa.unsynced.sent);
+            valids.push(pa.unsynced.valid);
+            used_ats.push(pa.used_at);
+            cooldowns.push(pa.unsynced.cooldown);
+        }
+
+        let states = sqlx::query_as(query)
+            .bind(&ids)
+            .bind(&sents)
+            .bind(&valids)
+            .bind(&used_ats)
+            .bind(&cooldowns)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(states)
+    }
+
+    /// Keys being leased by another replica are skipped rather than waited for.
+    async fn lease_auth(&self, provider: &str, except: Option<i32>) -> Result<Option<AuthState>> {
+        let query = r#"
+            UPDATE auth
+            SET sent = sent + 1,
+                used_at = NOW()
+            WHERE id = (
+                SELECT id FROM auth
+                WHERE provider = $1
+                  AND valid
+                  AND NOT cooldown
+                  AND (max = 0 OR sent < max)
+                  AND id IS DISTINCT FROM $2
+                ORDER BY used_at
+                LIMIT 1
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING id, sent, valid, used_at, cooldown
+        "#;
+
+        let state = sqlx::query_as(query)
+            .bind(provider)
+            .bind(except)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(state)
+    }
+
+    async fn reset_auth(&self, provider: &str) -> Result<u64> {
+        let result = sqlx::query("UPDATE auth SET sent = 0 WHERE provider = $1")
+            .bind(provider)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn save_proxies(&self, proxies: &[Arc<Proxy>]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM proxies").execute(&mut *tx).await?;
+
+        for proxy in proxies {
+            sqlx::query(
+                "INSERT INTO proxies (proxy_address, port, username, password) 
+                 VALUES ($1, $2, $3, $4)",
+            )
+            .bind(&proxy.proxy_address)
+            .bind(proxy.port as i32)
+            .bind(&proxy.username)
+            .bind(&proxy.password)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn load_proxies(&self) -> Result<Vec<Arc<Proxy>>> {
+        let db_proxies: Vec<DbProxy> = sqlx::query_as("SELECT * FROM proxies")
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(db_proxies
+            .into_iter()
+            .map(|p| Arc::new(Proxy::from(p)))
+            .collect())
+    }
+
+    async fn load_clients(&self) -> Result<Vec<DbClient>> {
+        let clients: Vec<DbClient> = sqlx::query_as("SELECT name, secret FROM clients")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(clients)
+    }
+
+    async fn load_model_rules(&self) -> Result<Vec<ModelRule>> {
+        let rules: Vec<ModelRule> =
+            sqlx::query_as("SELECT provider, client, pattern, allow FROM model_rules")
+                .fetch_all(&self.pool)
+                .await?;
+        Ok(rules)
+    }
+
+    async fn load_model_aliases(&self) -> Result<Vec<ModelAlias>> {
+        let aliases: Vec<ModelAlias> =
+            sqlx::query_as("SELECT provider, alias, model, rewrite_response FROM model_aliases")
+                .fetch_all(&self.pool)
+                .await?;
+        Ok(aliases)
+    }
+
+    async fn load_prompt_rules(&self) -> Result<Vec<PromptRule>> {
+        let rules: Vec<PromptRule> = sqlx::query_as(
+            "SELECT provider, model, client, action, content FROM prompt_rules ORDER BY id",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rules)
+    }
+
+    async fn get_cached_response(
+        &self,
+        key: &str,
+        since: DateTime<Utc>,
+    ) -> Result<Option<CachedResponse>> {
+        let response: Option<CachedResponse> = sqlx::query_as(
+            "SELECT response, created_at FROM response_cache WHERE key = $1 AND created_at >= $2",
+        )
+        .bind(key)
+        .bind(since)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(response)
+    }
+
+    async fn save_cached_response(
+        &self,
+        key: &str,
+        provider: &str,
+        model: &str,
+        response: &str,
+    ) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO response_cache (key, provider, model, response)
+             VALUES ($1, $2, $3, $4)
+             ON CONFLICT (key) DO UPDATE SET response = EXCLUDED.response, created_at = NOW()",
+        )
+        .bind(key)
+        .bind(provider)
+        .bind(model)
+        .bind(response)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn prune_cached_responses(&self, before: DateTime<Utc>) -> Result<u64> {
+        let result = sqlx::query("DELETE FROM response_cache WHERE created_at < $1")
+            .bind(before)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+}
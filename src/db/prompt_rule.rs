@@ -0,0 +1,13 @@
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct PromptRule {
+    /// `None` applies to all providers
+    pub provider: Option<String>,
+    /// Model id pattern, `None` applies to all models
+    pub model: Option<String>,
+    /// `None` applies to all clients
+    pub client: Option<String>,
+    /// `prepend_system`, `append_system` or `wrap_user`
+    pub action: String,
+    /// Template of the system message, or of the user content with `{{content}}`
+    pub content: String,
+}
@@ -0,0 +1,7 @@
+use chrono::{DateTime, Utc};
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct CachedResponse {
+    pub response: String,
+    pub created_at: DateTime<Utc>,
+}
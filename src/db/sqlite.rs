@@ -0,0 +1,358 @@
+use crate::{
+    db::{
+        auth::{AuthState, ProviderAuth},
+        model_access::{DbClient, ModelRule},
+        model_alias::ModelAlias,
+        prompt_rule::PromptRule,
+        proxy::DbProxy,
+        response_cache::CachedResponse,
+        StorageFn,
+    },
+    proxy::webshare::Proxy,
+};
+use chrono::{DateTime, Utc};
+use eyre::Result;
+use sqlx::{
+    sqlite::{SqliteConnectOptions, SqliteJournalMode},
+    SqlitePool,
+};
+use std::{str::FromStr as _, sync::Arc};
+
+/// Single-machine storage, e.g. `sqlite://lift-proxy.db`.
+/// Writes are serialized by SQLite, so leasing needs no row locks.
+pub struct SqliteStorage {
+    pool: SqlitePool,
+}
+
+impl SqliteStorage {
+    pub async fn connect(database_url: &str) -> Result<Self> {
+        let options = SqliteConnectOptions::from_str(database_url)?
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal);
+        let pool = SqlitePool::connect_with(options).await?;
+        sqlx::migrate!("./sqlite_migrations").run(&pool).await?;
+        Ok(Self { pool })
+    }
+}
+
+impl StorageFn for SqliteStorage {
+    async fn get_all_auth(&self) -> Result<Vec<ProviderAuth>> {
+        let all_auth: Vec<ProviderAuth> = sqlx::query_as("SELECT * FROM auth")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(all_auth)
+    }
+
+    async fn update_auth(&self, provider_auths: &[ProviderAuth]) -> Result<Vec<AuthState>> {
+        let query = r#"
+            UPDATE auth
+            SET sent = sent + $1,
+                valid = COALESCE($2, valid),
+                used_at = MAX(used_at, $3),
+                cooldown = COALESCE($4, cooldown)
+            WHERE id = $5
+            RETURNING id, sent, valid, used_at, cooldown
+        "#;
+
+        let mut tx = self.pool.begin().await?;
+        let mut states = Vec::with_capacity(provider_auths.len());
+        for pa in provider_auths {
+            let state = sqlx::query_as(query)
+                .bind(pa.unsynced.sent)
+                .bind(pa.unsynced.valid)
+                .bind(pa.used_at)
+                .bind(pa.unsynced.cooldown)
+                .bind(pa.id)
+                .fetch_optional(&mut *tx)
+                .await?;
+            states.extend(state);
+        }
+        tx.commit().await?;
+
+        Ok(states)
+    }
+
+    async fn lease_auth(&self, provider: &str, except: Option<i32>) -> Result<Option<AuthState>> {
+        let query = r#"
+            UPDATE auth
+            SET sent = sent + 1,
+                used_at = $3
+            WHERE id = (
+                SELECT id FROM auth
+                WHERE provider = $1
+                  AND valid
+                  AND NOT cooldown
+                  AND (max = 0 OR sent < max)
+                  AND id IS NOT $2
+                ORDER BY used_at
+                LIMIT 1
+            )
+            RETURNING id, sent, valid, used_at, cooldown
+        "#;
+
+        let state = sqlx::query_as(query)
+            .bind(provider)
+            .bind(except)
+            .bind(chrono::Utc::now())
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(state)
+    }
+
+    async fn reset_auth(&self, provider: &str) -> Result<u64> {
+        let result = sqlx::query("UPDATE auth SET sent = 0 WHERE provider = $1")
+            .bind(provider)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn save_proxies(&self, proxies: &[Arc<Proxy>]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM proxies").execute(&mut *tx).await?;
+
+        for proxy in proxies {
+            sqlx::query(
+                "INSERT INTO proxies (proxy_address, port, username, password)
+                 VALUES ($1, $2, $3, $4)",
+            )
+            .bind(&proxy.proxy_address)
+            .bind(proxy.port as i32)
+            .bind(&proxy.username)
+            .bind(&proxy.password)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn load_proxies(&self) -> Result<Vec<Arc<Proxy>>> {
+        let db_proxies: Vec<DbProxy> = sqlx::query_as("SELECT * FROM proxies")
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(db_proxies
+            .into_iter()
+            .map(|p| Arc::new(Proxy::from(p)))
+            .collect())
+    }
+
+    async fn load_clients(&self) -> Result<Vec<DbClient>> {
+        let clients: Vec<DbClient> = sqlx::query_as("SELECT name, secret FROM clients")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(clients)
+    }
+
+    async fn load_model_rules(&self) -> Result<Vec<ModelRule>> {
+        let rules: Vec<ModelRule> =
+            sqlx::query_as("SELECT provider, client, pattern, allow FROM model_rules")
+                .fetch_all(&self.pool)
+                .await?;
+        Ok(rules)
+    }
+
+    async fn load_model_aliases(&self) -> Result<Vec<ModelAlias>> {
+        let aliases: Vec<ModelAlias> =
+            sqlx::query_as("SELECT provider, alias, model, rewrite_response FROM model_aliases")
+                .fetch_all(&self.pool)
+                .await?;
+        Ok(aliases)
+    }
+
+    async fn load_prompt_rules(&self) -> Result<Vec<PromptRule>> {
+        let rules: Vec<PromptRule> = sqlx::query_as(
+            "SELECT provider, model, client, action, content FROM prompt_rules ORDER BY id",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rules)
+    }
+
+    async fn get_cached_response(
+        &self,
+        key: &str,
+        since: DateTime<Utc>,
+    ) -> Result<Option<CachedResponse>> {
+        let response: Option<CachedResponse> = sqlx::query_as(
+            "SELECT response, created_at FROM response_cache WHERE key = $1 AND created_at >= $2",
+        )
+        .bind(key)
+        .bind(since)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(response)
+    }
+
+    async fn save_cached_response(
+        &self,
+        key: &str,
+        provider: &str,
+        model: &str,
+        response: &str,
+    ) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO response_cache (key, provider, model, response, created_at)
+             VALUES ($1, $2, $3, $4, $5)
+             ON CONFLICT (key) DO UPDATE SET response = excluded.response, created_at = excluded.created_at",
+        )
+        .bind(key)
+        .bind(provider)
+        .bind(model)
+        .bind(response)
+        // RFC 3339, like the timestamps it is compared with
+        .bind(chrono::Utc::now())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn prune_cached_responses(&self, before: DateTime<Utc>) -> Result<u64> {
+        let result = sqlx::query("DELETE FROM response_cache WHERE created_at < $1")
+            .bind(before)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    /// An in-memory database, on a single connection since each one has its own database.
+    async fn storage() -> SqliteStorage {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::migrate!("./sqlite_migrations")
+            .run(&pool)
+            .await
+            .unwrap();
+        SqliteStorage { pool }
+    }
+
+    async fn insert_auth(storage: &SqliteStorage, key: &str, sent: i32, max: i32, used_at: &str) {
+        sqlx::query(
+            "INSERT INTO auth (provider, api_key, sent, max, used_at) VALUES ('p', $1, $2, $3, $4)",
+        )
+        .bind(key)
+        .bind(sent)
+        .bind(max)
+        .bind(used_at)
+        .execute(&storage.pool)
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn update_auth_increments_counters() {
+        let storage = storage().await;
+        insert_auth(&storage, "a", 5, 0, "2026-01-01T00:00:00+00:00").await;
+
+        let mut auth = storage.get_all_auth().await.unwrap().remove(0);
+        auth.unsynced.sent = 2;
+        storage.update_auth(&[auth.clone()]).await.unwrap();
+        auth.unsynced.valid = Some(false);
+        let states = storage.update_auth(&[auth]).await.unwrap();
+
+        assert_eq!(states.len(), 1);
+        assert_eq!(states[0].sent, 9);
+        assert!(!states[0].valid);
+        assert!(!states[0].cooldown);
+    }
+
+    #[tokio::test]
+    async fn lease_auth_picks_the_least_recently_used_key_with_quota() {
+        let storage = storage().await;
+        insert_auth(&storage, "exhausted", 3, 3, "2026-01-01T00:00:00+00:00").await;
+        insert_auth(&storage, "old", 0, 10, "2026-01-02T00:00:00+00:00").await;
+        insert_auth(&storage, "new", 0, 0, "2026-01-03T00:00:00+00:00").await;
+        insert_auth(&storage, "cooling", 0, 0, "2026-01-01T00:00:00+00:00").await;
+        sqlx::query("UPDATE auth SET cooldown = TRUE WHERE api_key = 'cooling'")
+            .execute(&storage.pool)
+            .await
+            .unwrap();
+        let id = |key: &'static str| {
+            let pool = storage.pool.clone();
+            async move {
+                sqlx::query_scalar::<_, i32>("SELECT id FROM auth WHERE api_key = $1")
+                    .bind(key)
+                    .fetch_one(&pool)
+                    .await
+                    .unwrap()
+            }
+        };
+        let (old, new) = (id("old").await, id("new").await);
+
+        let leased = storage.lease_auth("p", None).await.unwrap().unwrap();
+        assert_eq!((leased.id, leased.sent), (old, 1));
+        // leasing marks the key as used, so the other one is next
+        let leased = storage.lease_auth("p", None).await.unwrap().unwrap();
+        assert_eq!((leased.id, leased.sent), (new, 1));
+        let leased = storage.lease_auth("p", Some(old)).await.unwrap().unwrap();
+        assert_eq!((leased.id, leased.sent), (new, 2));
+
+        assert!(storage.lease_auth("p", Some(old)).await.unwrap().is_some());
+        assert!(storage.lease_auth("other", None).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn lease_auth_skips_keys_without_quota() {
+        let storage = storage().await;
+        insert_auth(&storage, "a", 0, 1, "2026-01-01T00:00:00+00:00").await;
+
+        assert!(storage.lease_auth("p", None).await.unwrap().is_some());
+        assert!(storage.lease_auth("p", None).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn saves_and_loads_proxies() {
+        let storage = storage().await;
+        let proxy = |address: &str| {
+            Arc::new(Proxy {
+                proxy_address: address.to_owned(),
+                port: 1080,
+                username: "user".to_owned(),
+                password: "pass".to_owned(),
+            })
+        };
+        storage
+            .save_proxies(&[proxy("1.1.1.1"), proxy("2.2.2.2")])
+            .await
+            .unwrap();
+        storage.save_proxies(&[proxy("3.3.3.3")]).await.unwrap();
+
+        let proxies = storage.load_proxies().await.unwrap();
+        assert_eq!(proxies.len(), 1);
+        assert_eq!(proxies[0].to_string(), proxy("3.3.3.3").to_string());
+    }
+
+    #[tokio::test]
+    async fn serves_and_prunes_cached_responses_by_age() {
+        let storage = storage().await;
+        storage
+            .save_cached_response("k", "p", "m", "{}")
+            .await
+            .unwrap();
+        let hour = chrono::TimeDelta::hours(1);
+        let (before, after) = (chrono::Utc::now() - hour, chrono::Utc::now() + hour);
+
+        let cached = storage.get_cached_response("k", before).await.unwrap();
+        assert_eq!(cached.unwrap().response, "{}");
+        assert!(storage
+            .get_cached_response("k", after)
+            .await
+            .unwrap()
+            .is_none());
+
+        assert_eq!(storage.prune_cached_responses(before).await.unwrap(), 0);
+        assert_eq!(storage.prune_cached_responses(after).await.unwrap(), 1);
+    }
+}
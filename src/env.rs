@@ -1,8 +1,31 @@
+use crate::proxy::policy::ProxyPolicy;
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+/// Interval of the background `sync_auth` if `AUTH_SYNC_INTERVAL` is unset
+const DEFAULT_AUTH_SYNC_SECONDS: u64 = 5 * 60;
+/// Age of the cached responses still served if `CACHE_MAX_AGE` is unset
+const DEFAULT_CACHE_MAX_AGE_SECONDS: u64 = 7 * 24 * 60 * 60;
+
 #[derive(Debug)]
 pub struct Env {
     pub database_url: String,
     pub webshare_token: String,
     pub auth_secret: String,
+    pub proxy_policies: HashMap<String, ProxyPolicy>,
+    pub google_native_api: bool,
+    pub non_streaming_models: HashSet<(String, String)>,
+    pub cache_capacity: usize,
+    pub cache_persist: bool,
+    /// `None` keeps cached responses forever
+    pub cache_max_age: Option<Duration>,
+    pub coalesce_requests: bool,
+    pub hedge_delays: HashMap<String, Duration>,
+    pub keep_alive_interval: Option<Duration>,
+    pub auth_sync_interval: Option<Duration>,
+    pub auth_leasing: bool,
 }
 
 impl Env {
@@ -11,8 +34,102 @@ impl Env {
             database_url: std::env::var("DATABASE_URL").expect("DATABASE_URL not set"),
             webshare_token: std::env::var("WEBSHARE_TOKEN").expect("WEBSHARE_TOKEN not set"),
             auth_secret: std::env::var("AUTH_SECRET").expect("AUTH_SECRET not set"),
+            proxy_policies: parse_proxy_policies(
+                &std::env::var("PROXY_POLICY").unwrap_or_default(),
+            ),
+            google_native_api: parse_bool("GOOGLE_NATIVE_API"),
+            non_streaming_models: parse_provider_models(
+                &std::env::var("NON_STREAMING_MODELS").unwrap_or_default(),
+            ),
+            cache_capacity: std::env::var("CACHE_CAPACITY")
+                .map(|value| value.parse().expect("Invalid CACHE_CAPACITY"))
+                .unwrap_or(0),
+            cache_persist: parse_bool("CACHE_PERSIST"),
+            cache_max_age: Some(
+                std::env::var("CACHE_MAX_AGE")
+                    .map(|value| value.parse().expect("Invalid CACHE_MAX_AGE"))
+                    .unwrap_or(DEFAULT_CACHE_MAX_AGE_SECONDS),
+            )
+            .filter(|seconds| *seconds > 0)
+            .map(Duration::from_secs),
+            coalesce_requests: parse_bool("COALESCE_REQUESTS"),
+            hedge_delays: parse_provider_durations(
+                "HEDGE_DELAY",
+                &std::env::var("HEDGE_DELAY").unwrap_or_default(),
+            ),
+            keep_alive_interval: std::env::var("KEEP_ALIVE_INTERVAL")
+                .ok()
+                .map(|value| value.parse().expect("Invalid KEEP_ALIVE_INTERVAL"))
+                .filter(|seconds| *seconds > 0)
+                .map(Duration::from_secs),
+            auth_sync_interval: Some(
+                std::env::var("AUTH_SYNC_INTERVAL")
+                    .map(|value| value.parse().expect("Invalid AUTH_SYNC_INTERVAL"))
+                    .unwrap_or(DEFAULT_AUTH_SYNC_SECONDS),
+            )
+            .filter(|seconds| *seconds > 0)
+            .map(Duration::from_secs),
+            auth_leasing: parse_bool("AUTH_LEASING"),
         };
         tracing::info!("Environment Loaded");
         env
     }
 }
+
+/// Parses per-provider proxy policies, e.g. `google=always,deepinfra=proxy_on_429`
+fn parse_proxy_policies(value: &str) -> HashMap<String, ProxyPolicy> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (provider, policy) = entry
+                .split_once('=')
+                .expect("PROXY_POLICY entries must be `provider=policy`");
+            let policy = policy.trim().parse().expect("Invalid PROXY_POLICY");
+            (provider.trim().to_lowercase(), policy)
+        })
+        .collect()
+}
+
+/// Parses per-provider model ids, e.g. `nvidia=some/model,chutes=other/model`
+fn parse_provider_models(value: &str) -> HashSet<(String, String)> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (provider, model) = entry
+                .split_once('=')
+                .expect("NON_STREAMING_MODELS entries must be `provider=model`");
+            (provider.trim().to_lowercase(), model.trim().to_owned())
+        })
+        .collect()
+}
+
+/// Parses per-provider durations in milliseconds, e.g. `google=1500,openrouter=3000`
+fn parse_provider_durations(key: &str, value: &str) -> HashMap<String, Duration> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (provider, millis) = entry
+                .split_once('=')
+                .unwrap_or_else(|| panic!("{} entries must be `provider=milliseconds`", key));
+            let millis = millis
+                .trim()
+                .parse()
+                .unwrap_or_else(|_| panic!("Invalid {}", key));
+            (
+                provider.trim().to_lowercase(),
+                Duration::from_millis(millis),
+            )
+        })
+        .collect()
+}
+
+/// Parses an optional boolean flag, `true` or `1` enables it
+fn parse_bool(key: &str) -> bool {
+    matches!(std::env::var(key).as_deref(), Ok("true") | Ok("1"))
+}
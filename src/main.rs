@@ -1,35 +1,87 @@
 mod app_state;
+mod cache;
+mod coalesce;
 mod db;
 mod env;
 mod middlewares;
 mod providers;
 mod proxy;
 mod routes;
+#[cfg(not(feature = "shuttle"))]
+mod shutdown;
+mod translate;
 mod utils;
 
 use app_state::AppState;
 use axum::{
     middleware,
-    routing::{get, post},
+    routing::{get, post, put},
     Router,
 };
-use middlewares::handle_auth;
-use providers::{auth::init_auth, init_providers};
+use cache::spawn_cache_prune;
+use middlewares::{handle_auth, require_admin};
+use providers::{
+    auth::{init_auth, spawn_auth_sync},
+    init_providers,
+    model_access::init_model_access,
+    model_alias::init_model_aliases,
+    prompt_rules::init_prompt_rules,
+};
 use proxy::webshare::init_proxies;
 use routes::{
+    anthropic_messages,
     auth_management::{pull_auth_route, sync_auth_route},
-    health, proxied_chat, proxied_models, toggle_show_chat,
+    health,
+    model_access::pull_model_access_route,
+    model_aliases::pull_model_aliases_route,
+    prompt_rules::pull_prompt_rules_route,
+    proxied_chat, proxied_completions, proxied_embeddings, proxied_images, proxied_models,
+    proxied_responses, proxied_speech, proxied_transcriptions, toggle_show_chat,
 };
+#[cfg(not(feature = "shuttle"))]
+use shutdown::{flush_state, shutdown_signal, SHUTDOWN_DEADLINE};
+#[cfg(not(feature = "shuttle"))]
+use std::future::IntoFuture as _;
 use std::sync::Arc;
+#[cfg(not(feature = "shuttle"))]
+use tokio::sync::Notify;
 
-async fn create_router() -> Router {
+async fn create_router() -> (Arc<AppState>, Router) {
     let app = Arc::new(AppState::new().await);
 
     init_providers(&app).await;
     init_auth(&app).await;
+    spawn_auth_sync(&app);
+    spawn_cache_prune(&app);
     init_proxies(&app).await;
+    init_model_aliases(&app).await;
+    init_model_access(&app).await;
+    init_prompt_rules(&app).await;
+
+    let admin = Router::new()
+        .route("/show_chat", post(toggle_show_chat))
+        .route("/auths", post(sync_auth_route).put(pull_auth_route))
+        .route("/aliases", put(pull_model_aliases_route))
+        .route("/access", put(pull_model_access_route))
+        .route("/prompts", put(pull_prompt_rules_route))
+        .route_layer(middleware::from_fn(require_admin));
 
-    Router::new()
+    let router = Router::new()
+        .route("/{provider_name}/v1/models", get(proxied_models))
+        .route("/{provider_name}/v1/chat/completions", post(proxied_chat))
+        .route("/{provider_name}/v1/completions", post(proxied_completions))
+        .route("/{provider_name}/v1/responses", post(proxied_responses))
+        .route("/{provider_name}/v1/messages", post(anthropic_messages))
+        .route("/{provider_name}/v1/embeddings", post(proxied_embeddings))
+        .route(
+            "/{provider_name}/v1/audio/transcriptions",
+            post(proxied_transcriptions),
+        )
+        .route("/{provider_name}/v1/audio/speech", post(proxied_speech))
+        .route(
+            "/{provider_name}/v1/images/generations",
+            post(proxied_images),
+        )
         .route(
             "/{proxy_flag}/{provider_name}/v1/models",
             get(proxied_models),
@@ -38,11 +90,39 @@ async fn create_router() -> Router {
             "/{proxy_flag}/{provider_name}/v1/chat/completions",
             post(proxied_chat),
         )
+        .route(
+            "/{proxy_flag}/{provider_name}/v1/completions",
+            post(proxied_completions),
+        )
+        .route(
+            "/{proxy_flag}/{provider_name}/v1/responses",
+            post(proxied_responses),
+        )
+        .route(
+            "/{proxy_flag}/{provider_name}/v1/messages",
+            post(anthropic_messages),
+        )
+        .route(
+            "/{proxy_flag}/{provider_name}/v1/embeddings",
+            post(proxied_embeddings),
+        )
+        .route(
+            "/{proxy_flag}/{provider_name}/v1/audio/transcriptions",
+            post(proxied_transcriptions),
+        )
+        .route(
+            "/{proxy_flag}/{provider_name}/v1/audio/speech",
+            post(proxied_speech),
+        )
+        .route(
+            "/{proxy_flag}/{provider_name}/v1/images/generations",
+            post(proxied_images),
+        )
         .route("/", get(health))
-        .route("/show_chat", post(toggle_show_chat))
-        .route("/auths", post(sync_auth_route).put(pull_auth_route))
+        .merge(admin)
         .layer(middleware::from_fn_with_state(app.clone(), handle_auth))
-        .with_state(app.clone())
+        .with_state(app.clone());
+    (app, router)
 }
 
 #[cfg(not(feature = "shuttle"))]
@@ -50,7 +130,29 @@ async fn create_router() -> Router {
 async fn main() {
     tracing_subscriber::fmt().init();
     let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
-    axum::serve(listener, create_router().await).await.unwrap();
+    let (app, router) = create_router().await;
+
+    // stops accepting connections once notified, then waits for the open ones
+    let shutdown = Arc::new(Notify::new());
+    let server = axum::serve(listener, router).with_graceful_shutdown({
+        let shutdown = shutdown.clone();
+        async move { shutdown.notified().await }
+    });
+    let mut server = std::pin::pin!(server.into_future());
+    tokio::select! {
+        res = &mut server => res.unwrap(),
+        _ = shutdown_signal() => {
+            shutdown.notify_one();
+            tracing::info!("[Shutdown] Draining requests for up to {:?}", SHUTDOWN_DEADLINE);
+            match tokio::time::timeout(SHUTDOWN_DEADLINE, server).await {
+                Ok(res) => res.unwrap(),
+                Err(_) => tracing::warn!("[Shutdown] Deadline reached, dropping open requests"),
+            }
+        }
+    }
+
+    flush_state(&app).await;
+    tracing::info!("[Shutdown] Done");
 }
 
 #[cfg(feature = "shuttle")]
@@ -61,5 +163,6 @@ async fn main(
     secrets.into_iter().for_each(|(key, val)| {
         std::env::set_var(key, val);
     });
-    Ok(create_router().await.into())
+    let (_, router) = create_router().await;
+    Ok(router.into())
 }
@@ -3,22 +3,56 @@ use axum::extract::State;
 use axum::http::StatusCode;
 use std::sync::Arc;
 
+/// The authenticated caller of a request.
+#[derive(Debug, Clone, Default)]
+pub struct Caller {
+    /// Client name, `None` for `AUTH_SECRET`
+    pub client: Option<String>,
+}
+
+impl Caller {
+    pub fn is_admin(&self) -> bool {
+        self.client.is_none()
+    }
+}
+
 pub async fn handle_auth(
     State(app): State<Arc<AppState>>,
-    req: axum::http::Request<axum::body::Body>,
+    mut req: axum::http::Request<axum::body::Body>,
     next: axum::middleware::Next,
 ) -> Result<axum::response::Response, StatusCode> {
-    match req.headers().get(axum::http::header::AUTHORIZATION) {
+    let headers = req.headers();
+    let token = match headers.get(axum::http::header::AUTHORIZATION) {
         Some(auth_header) => match auth_header.to_str() {
-            Ok(token) if token.starts_with("Bearer ") => {
-                let token = token.trim_start_matches("Bearer ");
-                match token {
-                    token if token == app.env.auth_secret => Ok(next.run(req).await),
-                    _ => Err(StatusCode::UNAUTHORIZED),
-                }
-            }
-            _ => Err(StatusCode::UNAUTHORIZED),
+            Ok(token) if token.starts_with("Bearer ") => token.trim_start_matches("Bearer "),
+            _ => return Err(StatusCode::UNAUTHORIZED),
+        },
+        // Anthropic clients send the key in `x-api-key`
+        None => match headers.get("x-api-key").map(|value| value.to_str()) {
+            Some(Ok(token)) => token,
+            _ => return Err(StatusCode::UNAUTHORIZED),
         },
-        None => Err(StatusCode::UNAUTHORIZED),
+    };
+    let caller = match token {
+        token if token == app.env.auth_secret => Caller { client: None },
+        token => match app.clients.lock().await.get(token) {
+            Some(name) => Caller {
+                client: Some(name.clone()),
+            },
+            None => return Err(StatusCode::UNAUTHORIZED),
+        },
+    };
+    req.extensions_mut().insert(caller);
+    Ok(next.run(req).await)
+}
+
+/// Restricts management routes to `AUTH_SECRET`.
+pub async fn require_admin(
+    req: axum::http::Request<axum::body::Body>,
+    next: axum::middleware::Next,
+) -> Result<axum::response::Response, StatusCode> {
+    match req.extensions().get::<Caller>() {
+        Some(caller) if caller.is_admin() => Ok(next.run(req).await),
+        _ => Err(StatusCode::FORBIDDEN),
     }
 }
@@ -1,3 +1,3 @@
 mod handle_auth;
 
-pub use handle_auth::handle_auth;
+pub use handle_auth::{handle_auth, require_admin, Caller};
@@ -1,6 +1,9 @@
 use crate::{
     app_state::AppState,
-    db::auth::{db_get_all_auth, db_update_auth, ProviderAuth},
+    db::{
+        auth::{AuthChanges, AuthState, ProviderAuth},
+        Storage, StorageFn as _,
+    },
     providers::ProviderFn as _,
 };
 use eyre::Result;
@@ -11,6 +14,8 @@ use std::{
 };
 
 const COOLDOWN_SECONDS: u64 = 30 * 60;
+/// Consecutive upstream failures putting an auth key on cooldown
+const MAX_FAILURES: u32 = 3;
 
 // Keep ProviderAuthVec here as it relates to the provider's in-memory state
 pub type ProviderAuthVec = Arc<RwLock<Vec<Arc<Mutex<ProviderAuth>>>>>;
@@ -18,7 +23,7 @@ pub type ProviderAuthVec = Arc<RwLock<Vec<Arc<Mutex<ProviderAuth>>>>>;
 /// Initializes the in-memory auth state by fetching from the database.
 pub async fn init_auth(app: &Arc<AppState>) {
     // Fetch auth data using the db module function
-    match db_get_all_auth(app).await {
+    match app.storage.get_all_auth().await {
         Ok(all_auth) => {
             tracing::info!("[Auth] {} auths initialized", all_auth.len());
             let providers = app.providers.lock().await;
@@ -26,7 +31,9 @@ pub async fn init_auth(app: &Arc<AppState>) {
                 if let Some(provider) = providers.get(&auth.provider) {
                     let provider_auth_vec = provider.get_auth();
                     let mut provider_auth_vec_locked = provider_auth_vec.write().unwrap();
-                    provider_auth_vec_locked.push(Arc::new(Mutex::new(auth)));
+                    let auth = Arc::new(Mutex::new(auth));
+                    resume_cooldown(app, &auth);
+                    provider_auth_vec_locked.push(auth);
                 } else {
                     tracing::warn!("Mismatched auth provider found during init: {:?}", auth);
                 }
@@ -46,29 +53,61 @@ pub async fn sync_auth(app: &Arc<AppState>) -> Result<()> {
     let providers_vec = providers.values().cloned().collect::<Vec<_>>();
 
     // Collect current state from memory
-    let provider_auths_in_memory = providers_vec
+    let auth_mutexes = providers_vec
         .iter()
-        .flat_map(|provider| {
-            let auth_vec = provider.get_auth();
-            let auth_vec_locked = auth_vec.read().unwrap();
-            auth_vec_locked
-                .iter()
-                .map(|auth_mutex| auth_mutex.lock().unwrap().clone())
-                .collect::<Vec<_>>()
-        })
+        .flat_map(|provider| provider.get_auth().read().unwrap().clone())
         .collect::<Vec<_>>();
 
-    // Update the database with the current in-memory state
-    let updated_rows = db_update_auth(app, &provider_auths_in_memory).await?;
+    // Update the database with the in-memory changes, and memory with the other replicas' ones
+    let updated_rows = write_auth(app, &auth_mutexes).await?;
     tracing::info!("Synced state to DB, updated {} rows", updated_rows);
+    let provider_auths_in_memory = auth_mutexes
+        .iter()
+        .map(|auth_mutex| {
+            let auth = auth_mutex.lock().unwrap();
+            (auth.id, auth.provider.clone())
+        })
+        .collect::<Vec<_>>();
 
     // Fetch the latest state from the database again
-    let db_auth = db_get_all_auth(app).await?;
+    let db_auth = app.storage.get_all_auth().await?;
+
+    // Drop auth records removed from the database, refresh the ones reconfigured there
+    let mut removed = 0;
+    for provider in &providers_vec {
+        let auth_vec = provider.get_auth();
+        let mut auth_vec_locked = auth_vec.write().unwrap();
+        auth_vec_locked.retain(|auth_mutex| {
+            let mut auth = auth_mutex.lock().unwrap();
+            let db = db_auth
+                .iter()
+                .find(|db| db.id == auth.id && db.provider == auth.provider);
+            match db {
+                Some(db) => {
+                    auth.api_key = db.api_key.clone();
+                    auth.max = db.max;
+                    auth.comments = db.comments.clone();
+                    true
+                }
+                None => {
+                    removed += 1;
+                    false
+                }
+            }
+        });
+    }
+    if removed > 0 {
+        tracing::info!("Dropped {} auths removed from database", removed);
+    }
 
     // Find new auth records in the database that are not yet in memory
     let new_auth = db_auth
         .iter()
-        .filter(|auth| !provider_auths_in_memory.iter().any(|pa| pa.id == auth.id))
+        .filter(|auth| {
+            !provider_auths_in_memory
+                .iter()
+                .any(|(id, provider)| *id == auth.id && *provider == auth.provider)
+        })
         .cloned()
         .collect::<Vec<_>>();
 
@@ -84,7 +123,9 @@ pub async fn sync_auth(app: &Arc<AppState>) -> Result<()> {
                     .iter()
                     .any(|pa| pa.lock().unwrap().id == auth.id)
                 {
-                    provider_auth_vec_locked.push(Arc::new(Mutex::new(auth)));
+                    let auth = Arc::new(Mutex::new(auth));
+                    resume_cooldown(app, &auth);
+                    provider_auth_vec_locked.push(auth);
                 }
             } else {
                 tracing::warn!("Mismatched auth provider found during sync: {:?}", auth);
@@ -95,11 +136,138 @@ pub async fn sync_auth(app: &Arc<AppState>) -> Result<()> {
     Ok(())
 }
 
+/// Writes the unsynced changes of auth keys to the database,
+/// then adopts the database state, which includes the changes of other replicas.
+pub async fn write_auth(
+    app: &Arc<AppState>,
+    auth_mutexes: &[Arc<Mutex<ProviderAuth>>],
+) -> Result<u64> {
+    let snapshots = auth_mutexes
+        .iter()
+        .map(|auth_mutex| {
+            let mut auth = auth_mutex.lock().unwrap();
+            let snapshot = auth.clone();
+            auth.unsynced = AuthChanges::default();
+            snapshot
+        })
+        .collect::<Vec<_>>();
+
+    let states = match app.storage.update_auth(&snapshots).await {
+        Ok(states) => states,
+        Err(e) => {
+            // keep the changes for the next write, after the ones made meanwhile
+            for (auth_mutex, snapshot) in auth_mutexes.iter().zip(snapshots) {
+                let mut auth = auth_mutex.lock().unwrap();
+                auth.unsynced.sent += snapshot.unsynced.sent;
+                auth.unsynced.valid = auth.unsynced.valid.or(snapshot.unsynced.valid);
+                auth.unsynced.cooldown = auth.unsynced.cooldown.or(snapshot.unsynced.cooldown);
+            }
+            return Err(e);
+        }
+    };
+
+    for state in &states {
+        let auth_mutex = auth_mutexes
+            .iter()
+            .find(|auth_mutex| auth_mutex.lock().unwrap().id == state.id);
+        if let Some(auth_mutex) = auth_mutex {
+            adopt_auth_state(app, auth_mutex, state);
+        }
+    }
+    Ok(states.len() as u64)
+}
+
+/// Sets the in-memory state of an auth key to its database state,
+/// except for changes made in memory that are not written yet.
+fn adopt_auth_state(app: &Arc<AppState>, auth_mutex: &Arc<Mutex<ProviderAuth>>, state: &AuthState) {
+    let mut auth = auth_mutex.lock().unwrap();
+    auth.sent = state.sent + auth.unsynced.sent;
+    auth.used_at = auth.used_at.max(state.used_at);
+    if auth.unsynced.valid.is_none() && auth.valid != state.valid {
+        tracing::info!(
+            "Auth key {} for {} marked as {} in database",
+            auth.id,
+            auth.provider,
+            if state.valid { "valid" } else { "invalid" }
+        );
+        auth.valid = state.valid;
+    }
+    if auth.unsynced.cooldown.is_none() && auth.cooldown != state.cooldown {
+        auth.cooldown = state.cooldown;
+        if state.cooldown {
+            tracing::info!(
+                "Auth key {} for {} on cooldown in database",
+                auth.id,
+                auth.provider
+            );
+            end_cooldown_after(app, auth_mutex.clone(), remaining_cooldown(&auth));
+        }
+    }
+}
+
+/// Debounce of auth change notifications, a bulk edit notifies once per statement
+const AUTH_NOTIFY_DEBOUNCE: Duration = Duration::from_secs(1);
+
+/// Runs `sync_auth` in the background, every `AUTH_SYNC_INTERVAL`,
+/// and whenever auth records are added, removed or reconfigured in the database.
+pub fn spawn_auth_sync(app: &Arc<AppState>) {
+    if let Some(interval) = app.env.auth_sync_interval {
+        let app = app.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            // the first tick completes immediately, right after `init_auth`
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                if let Err(e) = sync_auth(&app).await {
+                    tracing::error!("[Auth] Periodic sync failed: {}", e);
+                }
+            }
+        });
+    }
+
+    let app = app.clone();
+    tokio::spawn(async move {
+        // SQLite is for a single machine, changes only come from this one
+        let Storage::Postgres(storage) = &app.storage else {
+            return;
+        };
+        let mut listener = match storage.listen_auth().await {
+            Ok(listener) => listener,
+            Err(e) => {
+                tracing::error!("[Auth] Failed to listen for auth changes: {}", e);
+                return;
+            }
+        };
+        tracing::info!("[Auth] Listening for auth changes");
+        loop {
+            // reconnects if the connection is lost, notifications meanwhile are missed
+            // until the next periodic sync
+            match listener.recv().await {
+                Ok(notification) => {
+                    tokio::time::sleep(AUTH_NOTIFY_DEBOUNCE).await;
+                    while listener.next_buffered().is_some() {}
+                    tracing::info!("[Auth] {} in database", notification.payload());
+                    if let Err(e) = sync_auth(&app).await {
+                        tracing::error!("[Auth] Sync on change failed: {}", e);
+                    }
+                }
+                Err(e) => {
+                    tracing::error!("[Auth] Error receiving auth changes: {}", e);
+                    tokio::time::sleep(AUTH_NOTIFY_DEBOUNCE).await;
+                }
+            }
+        }
+    });
+}
+
 /// Updates the state of a specific auth key based on the HTTP response status.
+/// A successful request counts `cost` against the key's quota, the others nothing.
 pub fn update_auth_state_on_response(
     app: &Arc<AppState>,
     auth: &Option<Arc<Mutex<ProviderAuth>>>,
     status: &StatusCode,
+    cost: i32,
 ) {
     if let Some(auth_mutex) = auth {
         let auth_mutex_clone = auth_mutex.clone(); // Clone for potential async task
@@ -108,12 +276,20 @@ pub fn update_auth_state_on_response(
 
         match *status {
             StatusCode::OK => {
-                auth_locked.sent += 1;
+                // leased keys are charged 1 when picked
+                let cost = match app.env.auth_leasing {
+                    true => cost - 1,
+                    false => cost,
+                };
+                auth_locked.sent += cost;
+                auth_locked.unsynced.sent += cost;
+                auth_locked.failures = 0;
                 // Optional: info!() Log success if needed, but may be verbose
                 tracing::debug!("[{}] key {} authed", auth_locked.provider, auth_locked.id,);
             }
             StatusCode::UNAUTHORIZED => {
                 auth_locked.valid = false;
+                auth_locked.unsynced.valid = Some(false);
                 tracing::warn!(
                     "Auth key {} for {} marked as invalid due to UNAUTHORIZED",
                     auth_locked.id,
@@ -126,18 +302,7 @@ pub fn update_auth_state_on_response(
                     auth_locked.id,
                     auth_locked.provider
                 );
-                auth_locked.cooldown = true;
-                // Spawn a task to remove the cooldown flag after the duration
-                tokio::spawn(async move {
-                    tokio::time::sleep(Duration::from_secs(COOLDOWN_SECONDS)).await;
-                    let mut auth_schedule_locked = auth_mutex_clone.lock().unwrap();
-                    auth_schedule_locked.cooldown = false;
-                    tracing::info!(
-                        "Auth key {} for {} cooldown finished",
-                        auth_schedule_locked.id,
-                        auth_schedule_locked.provider
-                    );
-                });
+                start_cooldown(app, auth_mutex_clone.clone(), &mut auth_locked);
             }
             // Handle other potentially relevant error codes if necessary
             // e.g., 403 Forbidden might also indicate an invalid key in some APIs
@@ -148,17 +313,15 @@ pub fn update_auth_state_on_response(
                 auth_locked.provider
             ),
         };
+        // leased keys are charged 1 when picked, given back as failed requests are not charged
+        if *status != StatusCode::OK && app.env.auth_leasing {
+            auth_locked.sent -= 1;
+            auth_locked.unsynced.sent -= 1;
+        }
 
-        // Create a clone of the auth to update the DB
-        let auth_for_db = auth_locked.clone();
-
+        drop(auth_locked);
         // Update the auth in the database in background
-        let app = app.clone();
-        tokio::spawn(async move {
-            if let Err(e) = db_update_auth(&app, &vec![auth_for_db]).await {
-                tracing::error!("Failed to update auth in database: {}", e);
-            }
-        });
+        spawn_write_auth(app, auth_mutex_clone);
     } else {
         // This case might happen if the original request already had an Authorization header
         // or if no suitable key was found (e.g., all keys on cooldown or invalid).
@@ -167,3 +330,115 @@ pub fn update_auth_state_on_response(
         );
     }
 }
+
+/// Puts an auth key on cooldown, lifted after `COOLDOWN_SECONDS`.
+fn start_cooldown(
+    app: &Arc<AppState>,
+    auth_mutex: Arc<Mutex<ProviderAuth>>,
+    auth_locked: &mut ProviderAuth,
+) {
+    auth_locked.cooldown = true;
+    auth_locked.unsynced.cooldown = Some(true);
+    end_cooldown_after(app, auth_mutex, Duration::from_secs(COOLDOWN_SECONDS));
+}
+
+/// Restarts the timer of a key loaded on cooldown, which did not survive the restart.
+fn resume_cooldown(app: &Arc<AppState>, auth_mutex: &Arc<Mutex<ProviderAuth>>) {
+    let remaining = {
+        let auth_locked = auth_mutex.lock().unwrap();
+        if !auth_locked.cooldown {
+            return;
+        }
+        remaining_cooldown(&auth_locked)
+    };
+    end_cooldown_after(app, auth_mutex.clone(), remaining);
+}
+
+/// Time left of the cooldown of a key, which started when the key was last used.
+fn remaining_cooldown(auth: &ProviderAuth) -> Duration {
+    let elapsed = (chrono::Utc::now() - auth.used_at)
+        .to_std()
+        .unwrap_or_default();
+    Duration::from_secs(COOLDOWN_SECONDS).saturating_sub(elapsed)
+}
+
+fn end_cooldown_after(
+    app: &Arc<AppState>,
+    auth_mutex: Arc<Mutex<ProviderAuth>>,
+    duration: Duration,
+) {
+    let app = app.clone();
+    // Spawn a task to remove the cooldown flag after the duration
+    tokio::spawn(async move {
+        tokio::time::sleep(duration).await;
+        {
+            let mut auth_schedule_locked = auth_mutex.lock().unwrap();
+            // lifted meanwhile, e.g. by another replica
+            if !auth_schedule_locked.cooldown {
+                return;
+            }
+            auth_schedule_locked.cooldown = false;
+            auth_schedule_locked.unsynced.cooldown = Some(false);
+            tracing::info!(
+                "Auth key {} for {} cooldown finished",
+                auth_schedule_locked.id,
+                auth_schedule_locked.provider
+            );
+        }
+        if let Err(e) = write_auth(&app, &[auth_mutex]).await {
+            tracing::error!("Failed to update auth in database: {}", e);
+        }
+    });
+}
+
+/// Writes the changes of an auth key to the database in background.
+fn spawn_write_auth(app: &Arc<AppState>, auth_mutex: Arc<Mutex<ProviderAuth>>) {
+    let app = app.clone();
+    tokio::spawn(async move {
+        if let Err(e) = write_auth(&app, &[auth_mutex]).await {
+            tracing::error!("Failed to update auth in database: {}", e);
+        }
+    });
+}
+
+/// Records an upstream failure without a response (e.g. a timeout) against an auth key,
+/// putting it on cooldown after `MAX_FAILURES` consecutive failures.
+pub fn record_auth_failure(app: &Arc<AppState>, auth: &Option<Arc<Mutex<ProviderAuth>>>) {
+    let Some(auth_mutex) = auth else {
+        return;
+    };
+    let mut auth_locked = auth_mutex.lock().unwrap();
+    auth_locked.used_at = chrono::Utc::now();
+    auth_locked.failures += 1;
+    tracing::warn!(
+        "Auth key {} for {} failed {} times in a row",
+        auth_locked.id,
+        auth_locked.provider,
+        auth_locked.failures
+    );
+    if auth_locked.failures >= MAX_FAILURES {
+        auth_locked.failures = 0;
+        start_cooldown(app, auth_mutex.clone(), &mut auth_locked);
+    }
+
+    drop(auth_locked);
+    spawn_write_auth(app, auth_mutex.clone());
+}
+
+/// Gives back the 1 charged when a key was leased, for a request that ended up not sent
+/// or not served. Does nothing without `AUTH_LEASING`, where keys are charged on response.
+pub fn refund_lease(app: &Arc<AppState>, auth: &Option<Arc<Mutex<ProviderAuth>>>) {
+    let Some(auth_mutex) = auth else {
+        return;
+    };
+    if !app.env.auth_leasing {
+        return;
+    }
+    let mut auth_locked = auth_mutex.lock().unwrap();
+    auth_locked.sent -= 1;
+    auth_locked.unsynced.sent -= 1;
+    tracing::debug!("[Auth] Refunded the lease of key {}", auth_locked.id);
+
+    drop(auth_locked);
+    spawn_write_auth(app, auth_mutex.clone());
+}
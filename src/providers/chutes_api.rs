@@ -1,11 +1,16 @@
-use super::{ProviderAuthVec, ProviderFn};
-use crate::app_state::AppState;
+use super::{params::ParamRule, ProviderAuthVec, ProviderFn};
+use crate::{app_state::AppState, proxy::policy::ProxyPolicy};
 use axum::{body::Bytes, http::HeaderMap};
 use reqwest::{self as r, Url};
 use std::sync::Arc;
 
 const CHUTES_API_MODELS_URL: &str = "https://llm.chutes.ai/v1/models";
 const CHUTES_API_CHAT_URL: &str = "https://llm.chutes.ai/v1/chat/completions";
+const CHUTES_API_COMPLETIONS_URL: &str = "https://llm.chutes.ai/v1/completions";
+
+const PROXY_POLICY: ProxyPolicy = ProxyPolicy::Never;
+
+const PARAM_RULES: &[ParamRule] = &[ParamRule::Rename("max_completion_tokens", "max_tokens")];
 
 pub struct ChutesAPIProvider {
     pub auth_vec: ProviderAuthVec,
@@ -25,10 +30,22 @@ impl ProviderFn for ChutesAPIProvider {
         Url::parse(CHUTES_API_MODELS_URL).unwrap()
     }
 
-    fn chat_url(&self) -> Url {
+    fn chat_url(&self, _body: &Bytes) -> Url {
         Url::parse(CHUTES_API_CHAT_URL).unwrap()
     }
 
+    fn completions_url(&self) -> Option<Url> {
+        Some(Url::parse(CHUTES_API_COMPLETIONS_URL).unwrap())
+    }
+
+    fn param_rules(&self) -> &'static [ParamRule] {
+        PARAM_RULES
+    }
+
+    fn proxy_policy(&self) -> ProxyPolicy {
+        PROXY_POLICY
+    }
+
     fn get_header_modifier(&self, headers: &mut HeaderMap) {
         headers.clear();
     }
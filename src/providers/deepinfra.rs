@@ -2,12 +2,23 @@ use std::sync::Arc;
 
 use crate::app_state::AppState;
 
-use super::{ProviderAuthVec, ProviderFn};
+use super::{params::ParamRule, ProviderAuthVec, ProviderFn};
+use crate::proxy::policy::ProxyPolicy;
 use axum::{body::Bytes, http::HeaderMap};
 use reqwest::{Body, Url};
 
 const DEEPINFRA_MODELS_URL: &str = "https://api.deepinfra.com/v1/openai/models";
 const DEEPINFRA_CHAT_URL: &str = "https://api.deepinfra.com/v1/openai/chat/completions";
+const DEEPINFRA_COMPLETIONS_URL: &str = "https://api.deepinfra.com/v1/openai/completions";
+const DEEPINFRA_EMBEDDINGS_URL: &str = "https://api.deepinfra.com/v1/openai/embeddings";
+const DEEPINFRA_AUDIO_TRANSCRIPTIONS_URL: &str =
+    "https://api.deepinfra.com/v1/openai/audio/transcriptions";
+const DEEPINFRA_AUDIO_SPEECH_URL: &str = "https://api.deepinfra.com/v1/openai/audio/speech";
+const DEEPINFRA_IMAGES_URL: &str = "https://api.deepinfra.com/v1/openai/images/generations";
+
+const PROXY_POLICY: ProxyPolicy = ProxyPolicy::FallbackToProxyOn429;
+
+const PARAM_RULES: &[ParamRule] = &[ParamRule::Rename("max_completion_tokens", "max_tokens")];
 
 pub struct DeepinfraProvider;
 
@@ -23,10 +34,38 @@ impl ProviderFn for DeepinfraProvider {
         Url::parse(DEEPINFRA_MODELS_URL).unwrap()
     }
 
-    fn chat_url(&self) -> Url {
+    fn chat_url(&self, _body: &Bytes) -> Url {
         Url::parse(DEEPINFRA_CHAT_URL).unwrap()
     }
 
+    fn completions_url(&self) -> Option<Url> {
+        Some(Url::parse(DEEPINFRA_COMPLETIONS_URL).unwrap())
+    }
+
+    fn embeddings_url(&self) -> Option<Url> {
+        Some(Url::parse(DEEPINFRA_EMBEDDINGS_URL).unwrap())
+    }
+
+    fn audio_transcriptions_url(&self) -> Option<Url> {
+        Some(Url::parse(DEEPINFRA_AUDIO_TRANSCRIPTIONS_URL).unwrap())
+    }
+
+    fn audio_speech_url(&self) -> Option<Url> {
+        Some(Url::parse(DEEPINFRA_AUDIO_SPEECH_URL).unwrap())
+    }
+
+    fn images_url(&self) -> Option<Url> {
+        Some(Url::parse(DEEPINFRA_IMAGES_URL).unwrap())
+    }
+
+    fn param_rules(&self) -> &'static [ParamRule] {
+        PARAM_RULES
+    }
+
+    fn proxy_policy(&self) -> ProxyPolicy {
+        PROXY_POLICY
+    }
+
     fn get_header_modifier(&self, headers: &mut HeaderMap) {
         headers.clear();
     }
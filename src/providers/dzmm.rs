@@ -1,7 +1,13 @@
-use super::{auth::ProviderAuthVec, ProviderFn};
+use super::{
+    auth::ProviderAuthVec,
+    params::ParamRule,
+    timeouts::{Timeouts, DEFAULT_TIMEOUTS},
+    ProviderFn,
+};
 use crate::{
     app_state::AppState,
-    utils::data_types::{ChatBody, ChatResponse, Choice, Delta, StreamChunk},
+    proxy::policy::ProxyPolicy,
+    utils::{aggregate::aggregate_stream, data_types::ChatBody},
 };
 use axum::{body::Bytes, http::HeaderMap, response::IntoResponse as _};
 use chrono::{DateTime, Utc};
@@ -11,6 +17,24 @@ use std::sync::{Arc, Mutex};
 const DZMM_MODELS_URL: &str = "https://www.gpt4novel.com/api/xiaoshuoai/ext/v1/models";
 const DZMM_CHAT_URL: &str = "https://www.gpt4novel.com/api/xiaoshuoai/ext/v1/chat/completions";
 
+const PROXY_POLICY: ProxyPolicy = ProxyPolicy::FallbackToDirectOnProxyError;
+
+const PARAM_RULES: &[ParamRule] = &[
+    ParamRule::Rename("max_completion_tokens", "max_tokens"),
+    ParamRule::Drop("frequency_penalty"),
+    ParamRule::Drop("presence_penalty"),
+    ParamRule::Drop("logit_bias"),
+    ParamRule::Drop("tools"),
+    ParamRule::Drop("tool_choice"),
+    ParamRule::Clamp("n", 1.0, 1.0),
+];
+
+// DZMM writes long stories, and only streams once generation has started
+const TIMEOUTS: Timeouts = Timeouts {
+    first_byte: std::time::Duration::from_secs(300),
+    ..DEFAULT_TIMEOUTS
+};
+
 // DZMM Resets free quota at 11:00AM UTC
 const RESET_TIME: chrono::NaiveTime = chrono::NaiveTime::from_hms_opt(11, 0, 0).unwrap();
 
@@ -34,10 +58,22 @@ impl ProviderFn for DzmmProvider {
         Url::parse(DZMM_MODELS_URL).unwrap()
     }
 
-    fn chat_url(&self) -> Url {
+    fn chat_url(&self, _body: &Bytes) -> Url {
         Url::parse(DZMM_CHAT_URL).unwrap()
     }
 
+    fn param_rules(&self) -> &'static [ParamRule] {
+        PARAM_RULES
+    }
+
+    fn timeouts(&self) -> Timeouts {
+        TIMEOUTS
+    }
+
+    fn proxy_policy(&self) -> ProxyPolicy {
+        PROXY_POLICY
+    }
+
     fn get_header_modifier(&self, headers: &mut HeaderMap) {
         headers.clear();
     }
@@ -78,50 +114,13 @@ impl ProviderFn for DzmmProvider {
             }
         };
 
-        if body.stream.unwrap_or(false) {
-            crate::utils::stream_body::get_response_stream(resp).await
+        let res = crate::utils::stream_body::get_response_stream(resp).await;
+        if body.stream.value().copied().unwrap_or(false) {
+            res
         } else {
+            // DZMM streams regardless of the request
             tracing::info!("Parsing DZMM non-streaming response");
-            let mut resp = resp;
-            let mut resp_body = String::new();
-            while let Ok(Some(chunk)) = resp.chunk().await {
-                let resp_text = String::from_utf8_lossy(&chunk);
-                let Some(resp_text) = resp_text.strip_prefix("data: ") else {
-                    continue;
-                };
-                if resp_text == "[DONE]" {
-                    break;
-                }
-                let Ok(chunk) = serde_json::from_str::<StreamChunk>(resp_text) else {
-                    continue;
-                };
-                let Some(choice) = chunk.choices.first() else {
-                    continue;
-                };
-                resp_body.push_str(&choice.delta.as_ref().unwrap().content);
-            }
-
-            (
-                axum::http::StatusCode::OK,
-                serde_json::to_string(&ChatResponse {
-                    id: None,
-                    choices: vec![Choice {
-                        delta: None,
-                        index: Some(0),
-                        message: Some(Delta {
-                            role: Some("assistant".to_owned()),
-                            content: resp_body,
-                            match_stop: None,
-                            finish_reason: Some("stop".to_owned()),
-                        }),
-                    }],
-                    model: None,
-                    object: None,
-                    created: None,
-                })
-                .unwrap(),
-            )
-                .into_response()
+            aggregate_stream(res, true).await
         }
     }
 }
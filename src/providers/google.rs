@@ -1,6 +1,7 @@
-use crate::app_state::AppState;
+use crate::{app_state::AppState, translate::gemini};
 
-use super::{ProviderAuthVec, ProviderFn};
+use super::{params::ParamRule, ProviderAuthVec, ProviderFn};
+use crate::proxy::policy::ProxyPolicy;
 use axum::{body::Bytes, http::HeaderMap};
 use chrono::{DateTime, Utc};
 use reqwest::{self as r, Url};
@@ -9,6 +10,22 @@ use std::sync::{Arc, Mutex};
 const GOOGLE_MODELS_URL: &str = "https://generativelanguage.googleapis.com/v1beta/openai/models";
 const GOOGLE_CHAT_URL: &str =
     "https://generativelanguage.googleapis.com/v1beta/openai/chat/completions";
+const GOOGLE_EMBEDDINGS_URL: &str =
+    "https://generativelanguage.googleapis.com/v1beta/openai/embeddings";
+const GOOGLE_IMAGES_URL: &str =
+    "https://generativelanguage.googleapis.com/v1beta/openai/images/generations";
+const GOOGLE_NATIVE_MODELS_URL: &str = "https://generativelanguage.googleapis.com/v1beta/models";
+
+const PROXY_POLICY: ProxyPolicy = ProxyPolicy::FallbackToDirectOnProxyError;
+
+const PARAM_RULES: &[ParamRule] = &[
+    ParamRule::Rename("max_completion_tokens", "max_tokens"),
+    ParamRule::Drop("logit_bias"),
+    ParamRule::Drop("user"),
+];
+
+// Each generated image weighs more than a chat completion against the quota
+const IMAGE_COST: i32 = 10;
 
 const RESET_TIME: chrono::NaiveTime = chrono::NaiveTime::from_hms_opt(7, 0, 0).unwrap();
 
@@ -16,11 +33,14 @@ pub struct GoogleProvider {
     pub app: Arc<AppState>,
     pub auth_vec: ProviderAuthVec,
     pub last_authed_at: Arc<Mutex<DateTime<Utc>>>,
+    /// Use the native `generateContent` API instead of the OpenAI compatibility API
+    pub native: bool,
 }
 
 impl GoogleProvider {
     pub fn new(app: Arc<AppState>) -> Self {
         Self {
+            native: app.env.google_native_api,
             app,
             auth_vec: ProviderAuthVec::default(),
             last_authed_at: Arc::new(Mutex::new(Utc::now())),
@@ -33,8 +53,44 @@ impl ProviderFn for GoogleProvider {
         Url::parse(GOOGLE_MODELS_URL).unwrap()
     }
 
-    fn chat_url(&self) -> Url {
-        Url::parse(GOOGLE_CHAT_URL).unwrap()
+    fn chat_url(&self, body: &Bytes) -> Url {
+        if !self.native {
+            return Url::parse(GOOGLE_CHAT_URL).unwrap();
+        }
+        let chat = serde_json::from_slice(body).unwrap_or_default();
+        let url = match gemini::is_stream(&chat) {
+            true => format!(
+                "{}/{}:streamGenerateContent?alt=sse",
+                GOOGLE_NATIVE_MODELS_URL,
+                gemini::model_id(&chat)
+            ),
+            false => format!(
+                "{}/{}:generateContent",
+                GOOGLE_NATIVE_MODELS_URL,
+                gemini::model_id(&chat)
+            ),
+        };
+        Url::parse(&url).unwrap()
+    }
+
+    fn embeddings_url(&self) -> Option<Url> {
+        Some(Url::parse(GOOGLE_EMBEDDINGS_URL).unwrap())
+    }
+
+    fn images_url(&self) -> Option<Url> {
+        Some(Url::parse(GOOGLE_IMAGES_URL).unwrap())
+    }
+
+    fn image_cost(&self) -> i32 {
+        IMAGE_COST
+    }
+
+    fn param_rules(&self) -> &'static [ParamRule] {
+        PARAM_RULES
+    }
+
+    fn proxy_policy(&self) -> ProxyPolicy {
+        PROXY_POLICY
     }
 
     fn get_header_modifier(&self, headers: &mut HeaderMap) {
@@ -47,7 +103,26 @@ impl ProviderFn for GoogleProvider {
     }
 
     fn body_modifier(&self, body: Bytes) -> r::Body {
-        r::Body::from(body)
+        if !self.native {
+            return r::Body::from(body);
+        }
+        match serde_json::from_slice(&body) {
+            Ok(chat) => r::Body::from(gemini::to_generate_content(&chat).to_string()),
+            Err(e) => {
+                tracing::warn!("Error parsing body: {}", e);
+                r::Body::from(body)
+            }
+        }
+    }
+
+    fn auth_modifier(&self, headers: &mut HeaderMap, api_key: &str) {
+        match self.native {
+            true => headers.insert("x-goog-api-key", api_key.parse().unwrap()),
+            false => headers.insert(
+                "authorization",
+                format!("Bearer {}", api_key).parse().unwrap(),
+            ),
+        };
     }
 
     fn get_auth(&self) -> ProviderAuthVec {
@@ -65,9 +140,12 @@ impl ProviderFn for GoogleProvider {
 
     async fn get_response(
         &self,
-        _body: axum::body::Bytes,
+        body: axum::body::Bytes,
         resp: reqwest::Response,
     ) -> axum::http::Response<axum::body::Body> {
-        crate::utils::stream_body::get_response_stream(resp).await
+        match self.native {
+            true => gemini::translate_response(&body, resp).await,
+            false => crate::utils::stream_body::get_response_stream(resp).await,
+        }
     }
 }
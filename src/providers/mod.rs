@@ -1,4 +1,9 @@
 pub mod auth;
+pub mod model_access;
+pub mod model_alias;
+pub mod params;
+pub mod prompt_rules;
+pub mod timeouts;
 
 mod chutes_api;
 mod deepinfra;
@@ -9,7 +14,8 @@ mod openrouter;
 
 use crate::{
     app_state::AppState,
-    db::auth::{db_reset_auth, ProviderAuth},
+    db::{auth::ProviderAuth, StorageFn as _},
+    proxy::policy::ProxyPolicy,
 };
 use auth::ProviderAuthVec;
 use axum::{body::Bytes, http::HeaderMap};
@@ -20,16 +26,58 @@ use dzmm::DzmmProvider;
 use google::GoogleProvider;
 use nvidia::NvidiaProvider;
 use openrouter::OpenRouterProvider;
+use params::ParamRule;
 use reqwest::{Body, Url};
 use std::sync::{Arc, Mutex};
+use timeouts::{Timeouts, DEFAULT_TIMEOUTS};
 
 pub trait ProviderFn {
     fn models_url(&self) -> Url;
-    fn chat_url(&self) -> Url;
+    fn chat_url(&self, body: &Bytes) -> Url;
+    fn completions_url(&self) -> Option<Url> {
+        None
+    }
+    fn responses_url(&self) -> Option<Url> {
+        None
+    }
+    fn embeddings_url(&self) -> Option<Url> {
+        None
+    }
+    fn audio_transcriptions_url(&self) -> Option<Url> {
+        None
+    }
+    fn audio_speech_url(&self) -> Option<Url> {
+        None
+    }
+    fn images_url(&self) -> Option<Url> {
+        None
+    }
+    /// Quota charged to the auth key for each generated image
+    fn image_cost(&self) -> i32 {
+        1
+    }
+    /// Whether the upstream can stream chat completions for the model,
+    /// streaming requests for other models are sent without streaming and synthesized
+    fn supports_streaming(&self, _model: &str) -> bool {
+        true
+    }
+    /// Rules normalizing chat completions parameters the upstream rejects
+    fn param_rules(&self) -> &'static [ParamRule] {
+        &[]
+    }
+    /// Connect, first byte and idle stream deadlines of requests to the upstream
+    fn timeouts(&self) -> Timeouts {
+        DEFAULT_TIMEOUTS
+    }
+    fn proxy_policy(&self) -> ProxyPolicy;
     fn get_header_modifier(&self, headers: &mut HeaderMap);
     fn post_header_modifier(&self, headers: &mut HeaderMap);
     fn body_modifier(&self, body: Bytes) -> Body;
     fn get_auth(&self) -> ProviderAuthVec;
+    fn auth_modifier(&self, headers: &mut HeaderMap, api_key: &str) {
+        let value = format!("Bearer {}", api_key);
+        headers.insert("authorization", value.parse().unwrap());
+    }
     async fn get_response(
         &self,
         body: axum::body::Bytes,
@@ -38,7 +86,8 @@ pub trait ProviderFn {
 }
 
 impl Provider {
-    pub fn pick_auth(&self) -> Option<Arc<Mutex<ProviderAuth>>> {
+    /// Picks an auth key other than `except`, e.g. for a hedged request.
+    pub fn pick_auth_except(&self, except: Option<i32>) -> Option<Arc<Mutex<ProviderAuth>>> {
         let auth = self.get_auth();
         let mut auth_vec = auth.write().unwrap();
 
@@ -52,9 +101,12 @@ impl Provider {
         // find a valid auth
         let index = auth_vec.iter().position(|auth| {
             let auth = auth.lock().unwrap();
-            // check if auth is valid, if it has available quota
+            if Some(auth.id) == except {
+                return false;
+            }
+            // check if auth is valid, not on cooldown, if it has available quota
             // if max is 0, then it is unlimited
-            auth.valid && (auth.max == 0 || auth.sent < auth.max)
+            auth.valid && !auth.cooldown && (auth.max == 0 || auth.sent < auth.max)
         });
 
         match index {
@@ -63,12 +115,68 @@ impl Provider {
         }
     }
 
-    pub fn apply_auth(&self, headers: &mut HeaderMap) -> Option<Arc<Mutex<ProviderAuth>>> {
-        let picked_auth = self.pick_auth();
-        if let Some(auth) = &picked_auth {
+    /// Picks an auth key other than `except`, leased from the database if `AUTH_LEASING` is on.
+    /// Leased keys are charged 1 when picked, falling back to a key picked in memory,
+    /// and charged the same way, if the database cannot lease one.
+    pub async fn lease_auth(
+        &self,
+        app: &Arc<AppState>,
+        provider_name: &str,
+        except: Option<i32>,
+    ) -> Option<Arc<Mutex<ProviderAuth>>> {
+        if !app.env.auth_leasing {
+            return self.pick_auth_except(except);
+        }
+
+        match app.storage.lease_auth(provider_name, except).await {
+            Ok(Some(state)) => {
+                let auth = self.get_auth();
+                let auth_vec = auth.read().unwrap();
+                let leased = auth_vec
+                    .iter()
+                    .find(|auth| auth.lock().unwrap().id == state.id)
+                    .cloned();
+                if let Some(auth_mutex) = &leased {
+                    let mut auth = auth_mutex.lock().unwrap();
+                    auth.sent = state.sent + auth.unsynced.sent;
+                    auth.used_at = state.used_at;
+                    return leased.clone();
+                }
+                tracing::warn!("[Auth] Leased key {} is not synced yet", state.id);
+            }
+            Ok(None) => return None,
+            Err(e) => tracing::error!("[Auth] Failed to lease a key: {}", e),
+        }
+
+        let picked_auth = self.pick_auth_except(except);
+        if let Some(auth_mutex) = &picked_auth {
+            let mut auth = auth_mutex.lock().unwrap();
+            auth.sent += 1;
+            auth.unsynced.sent += 1;
+            auth.used_at = Utc::now();
+        }
+        picked_auth
+    }
+
+    pub async fn apply_auth(
+        &self,
+        app: &Arc<AppState>,
+        provider_name: &str,
+        headers: &mut HeaderMap,
+    ) -> Option<Arc<Mutex<ProviderAuth>>> {
+        let picked_auth = self.lease_auth(app, provider_name, None).await;
+        self.apply_picked_auth(headers, &picked_auth);
+        picked_auth
+    }
+
+    pub fn apply_picked_auth(
+        &self,
+        headers: &mut HeaderMap,
+        picked_auth: &Option<Arc<Mutex<ProviderAuth>>>,
+    ) {
+        if let Some(auth) = picked_auth {
             let auth = auth.lock().unwrap();
-            let value = format!("Bearer {}", auth.api_key);
-            headers.insert("authorization", value.parse().unwrap());
+            self.auth_modifier(headers, &auth.api_key);
             tracing::info!(
                 "[Auth] {}: {} - {}/{} # {}",
                 auth.provider,
@@ -78,7 +186,6 @@ impl Provider {
                 auth.comments.clone().unwrap_or("".to_owned())
             );
         }
-        picked_auth
     }
 
     pub fn handle_auth_reset(
@@ -115,7 +222,7 @@ impl Provider {
                 }
             }
 
-            match db_reset_auth(&app, &provider).await {
+            match app.storage.reset_auth(&provider).await {
                 Ok(rows) => tracing::info!("Auth reset for {}, {} rows updated", provider, rows),
                 Err(e) => tracing::error!("Error resetting auth: {}", e),
             }
@@ -168,9 +275,81 @@ macro_rules! impl_provider {
                 }
             }
 
-            fn chat_url(&self) -> Url {
+            fn chat_url(&self, body: &Bytes) -> Url {
+                match self {
+                    $(Provider::$name(p) => p.chat_url(body),)*
+                }
+            }
+
+            fn completions_url(&self) -> Option<Url> {
+                match self {
+                    $(Provider::$name(p) => p.completions_url(),)*
+                }
+            }
+
+            fn responses_url(&self) -> Option<Url> {
+                match self {
+                    $(Provider::$name(p) => p.responses_url(),)*
+                }
+            }
+
+            fn embeddings_url(&self) -> Option<Url> {
+                match self {
+                    $(Provider::$name(p) => p.embeddings_url(),)*
+                }
+            }
+
+            fn audio_transcriptions_url(&self) -> Option<Url> {
+                match self {
+                    $(Provider::$name(p) => p.audio_transcriptions_url(),)*
+                }
+            }
+
+            fn audio_speech_url(&self) -> Option<Url> {
+                match self {
+                    $(Provider::$name(p) => p.audio_speech_url(),)*
+                }
+            }
+
+            fn images_url(&self) -> Option<Url> {
+                match self {
+                    $(Provider::$name(p) => p.images_url(),)*
+                }
+            }
+
+            fn image_cost(&self) -> i32 {
+                match self {
+                    $(Provider::$name(p) => p.image_cost(),)*
+                }
+            }
+
+            fn supports_streaming(&self, model: &str) -> bool {
+                match self {
+                    $(Provider::$name(p) => p.supports_streaming(model),)*
+                }
+            }
+
+            fn param_rules(&self) -> &'static [ParamRule] {
+                match self {
+                    $(Provider::$name(p) => p.param_rules(),)*
+                }
+            }
+
+            fn timeouts(&self) -> Timeouts {
+                match self {
+                    $(Provider::$name(p) => p.timeouts(),)*
+                }
+            }
+
+            fn proxy_policy(&self) -> ProxyPolicy {
+                match self {
+                    $(Provider::$name(p) => p.proxy_policy(),)*
+                }
+            }
+
+            fn auth_modifier(&self, headers: &mut HeaderMap, api_key: &str) {
                 match self {
-                    $(Provider::$name(p) => p.chat_url(),)*
+                    $(Provider::$name(p) => p.auth_modifier(headers, api_key),)*
                 }
             }
 
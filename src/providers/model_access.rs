@@ -0,0 +1,143 @@
+use crate::{
+    app_state::AppState,
+    db::{model_access::ModelRule, StorageFn as _},
+    middlewares::Caller,
+    utils::pattern::glob_match,
+};
+use axum::{
+    body::{Body, Bytes},
+    http::{header, Response, StatusCode},
+    response::IntoResponse,
+};
+use eyre::Result;
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+/// Loads the clients and model rules from the database, replacing the ones in memory.
+pub async fn load_model_access(app: &Arc<AppState>) -> Result<(usize, usize)> {
+    let clients = app.storage.load_clients().await?;
+    let rules = app.storage.load_model_rules().await?;
+    let counts = (clients.len(), rules.len());
+    *app.clients.lock().await = clients
+        .into_iter()
+        .map(|client| (client.secret, client.name))
+        .collect();
+    *app.model_rules.lock().await = rules;
+    Ok(counts)
+}
+
+pub async fn init_model_access(app: &Arc<AppState>) {
+    match load_model_access(app).await {
+        Ok((clients, rules)) => {
+            tracing::info!(
+                "[Access] {} clients, {} model rules initialized",
+                clients,
+                rules
+            )
+        }
+        Err(e) => panic!("Failed to initialize model access from database: {}", e),
+    }
+}
+
+/// The model rules applying to a caller of a provider.
+pub struct ModelAccess {
+    rules: Vec<ModelRule>,
+}
+
+impl ModelAccess {
+    pub async fn new(app: &Arc<AppState>, provider_name: &str, caller: &Caller) -> Self {
+        let rules = app
+            .model_rules
+            .lock()
+            .await
+            .iter()
+            .filter(|rule| rule.provider.as_ref().is_none_or(|p| p == provider_name))
+            .filter(|rule| rule.client.is_none() || rule.client == caller.client)
+            .cloned()
+            .collect();
+        Self { rules }
+    }
+
+    pub fn is_unrestricted(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Denied if any deny rule matches, otherwise allowed if there are no allow rules
+    /// or any of them matches.
+    pub fn allows(&self, model: &str) -> bool {
+        let matching = |allow: bool| {
+            self.rules
+                .iter()
+                .filter(move |rule| rule.allow == allow)
+                .map(|rule| glob_match(&rule.pattern, model))
+        };
+        if matching(false).any(|matched| matched) {
+            return false;
+        }
+        let mut allows = matching(true).peekable();
+        allows.peek().is_none() || allows.any(|matched| matched)
+    }
+}
+
+/// An OpenAI style error for a model the caller may not use.
+pub fn model_forbidden(model: &str) -> Response<Body> {
+    tracing::warn!("Model not allowed: {}", model);
+    (
+        StatusCode::FORBIDDEN,
+        [(header::CONTENT_TYPE, "application/json")],
+        json!({
+            "error": {
+                "message": format!("The model `{}` is not allowed", model),
+                "type": "invalid_request_error",
+                "param": "model",
+                "code": "model_not_allowed",
+            }
+        })
+        .to_string(),
+    )
+        .into_response()
+}
+
+/// An OpenAI style error for a request whose model cannot be checked, e.g. a streamed upload,
+/// sent by a caller restricted to some models.
+pub fn unknown_model_forbidden() -> Response<Body> {
+    tracing::warn!("Model of a streamed upload cannot be checked for a restricted caller");
+    (
+        StatusCode::FORBIDDEN,
+        [(header::CONTENT_TYPE, "application/json")],
+        json!({
+            "error": {
+                "message": "Streamed uploads are not allowed for callers restricted to some models",
+                "type": "invalid_request_error",
+                "param": "model",
+                "code": "model_not_allowed",
+            }
+        })
+        .to_string(),
+    )
+        .into_response()
+}
+
+/// Removes the models the caller may not use from a model list response.
+pub async fn filter_models(access: &ModelAccess, res: Response<Body>) -> Response<Body> {
+    if access.is_unrestricted() || !res.status().is_success() {
+        return res;
+    }
+
+    let (mut parts, body) = res.into_parts();
+    parts.headers.remove(header::CONTENT_LENGTH);
+    let body = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(e) => return (StatusCode::BAD_GATEWAY, e.to_string()).into_response(),
+    };
+    let body = match serde_json::from_slice::<Value>(&body) {
+        Ok(mut models) => {
+            if let Some(data) = models["data"].as_array_mut() {
+                data.retain(|model| access.allows(model["id"].as_str().unwrap_or_default()));
+            }
+            Bytes::from(models.to_string())
+        }
+        Err(_) => body,
+    };
+    Response::from_parts(parts, Body::from(body))
+}
@@ -0,0 +1,132 @@
+use crate::{
+    app_state::AppState,
+    db::{model_alias::ModelAlias, StorageFn as _},
+    utils::sse::{format_event, is_event_stream, SseEvent, SseParser},
+};
+use axum::{
+    body::{Body, Bytes},
+    http::{header, Response, StatusCode},
+    response::IntoResponse,
+};
+use eyre::Result;
+use futures::StreamExt as _;
+use serde_json::Value;
+use std::sync::Arc;
+
+/// Loads the model aliases from the database, replacing the ones in memory.
+pub async fn load_model_aliases(app: &Arc<AppState>) -> Result<usize> {
+    let aliases = app.storage.load_model_aliases().await?;
+    let count = aliases.len();
+    let mut model_aliases = app.model_aliases.lock().await;
+    *model_aliases = aliases
+        .into_iter()
+        .map(|alias| ((alias.provider.clone(), alias.alias.clone()), alias))
+        .collect();
+    Ok(count)
+}
+
+pub async fn init_model_aliases(app: &Arc<AppState>) {
+    match load_model_aliases(app).await {
+        Ok(count) => tracing::info!("[Alias] {} model aliases initialized", count),
+        Err(e) => panic!("Failed to initialize model aliases from database: {}", e),
+    }
+}
+
+/// Replaces an aliased `model` of a request body with the provider's model id,
+/// returning the applied alias.
+pub async fn resolve_model_alias(
+    app: &Arc<AppState>,
+    provider_name: &str,
+    body: Option<&mut Value>,
+) -> Option<ModelAlias> {
+    let body = body?;
+    let model = body["model"].as_str()?;
+    let alias = app
+        .model_aliases
+        .lock()
+        .await
+        .get(&(provider_name.to_owned(), model.to_owned()))
+        .cloned()?;
+    body["model"] = Value::String(alias.model.clone());
+    Some(alias)
+}
+
+fn set_model(json: &mut Value, model: &str) {
+    if let Some(json) = json.as_object_mut() {
+        if json.contains_key("model") {
+            json.insert("model".to_owned(), Value::String(model.to_owned()));
+        }
+    }
+}
+
+/// Rewrites the `model` of the chunks of a chat completion stream.
+struct ModelRewrite {
+    parser: SseParser,
+    model: String,
+}
+
+impl ModelRewrite {
+    fn push(&mut self, chunk: &[u8]) -> Vec<Bytes> {
+        let events = self.parser.push(chunk);
+        events
+            .into_iter()
+            .map(|event| self.rewrite(event))
+            .collect()
+    }
+
+    fn finish(&mut self) -> Vec<Bytes> {
+        let event = self.parser.finish();
+        event.into_iter().map(|event| self.rewrite(event)).collect()
+    }
+
+    fn rewrite(&self, event: SseEvent) -> Bytes {
+        let data = match serde_json::from_str::<Value>(&event.data) {
+            Ok(mut chunk) if !event.is_done() => {
+                set_model(&mut chunk, &self.model);
+                chunk.to_string()
+            }
+            _ => event.data,
+        };
+        format_event(event.event.as_deref(), &data)
+    }
+}
+
+/// Rewrites the `model` of a chat completion response, streamed or not, to the alias.
+pub async fn rewrite_response_model(res: Response<Body>, model: String) -> Response<Body> {
+    if !res.status().is_success() {
+        return res;
+    }
+
+    let (mut parts, body) = res.into_parts();
+    parts.headers.remove(header::CONTENT_LENGTH);
+
+    if is_event_stream(&parts.headers) {
+        let mut state = ModelRewrite {
+            parser: SseParser::new(),
+            model,
+        };
+        let stream = body
+            .into_data_stream()
+            .map(Some)
+            .chain(futures::stream::once(async { None }))
+            .map(move |chunk| match chunk {
+                Some(Ok(chunk)) => Ok(Bytes::from(state.push(&chunk).concat())),
+                Some(Err(e)) => Err(e),
+                None => Ok(Bytes::from(state.finish().concat())),
+            });
+        return Response::from_parts(parts, Body::from_stream(stream));
+    }
+
+    let body = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(e) => return (StatusCode::BAD_GATEWAY, e.to_string()).into_response(),
+    };
+    let body = match serde_json::from_slice::<Value>(&body) {
+        Ok(mut json) => {
+            set_model(&mut json, &model);
+            Bytes::from(json.to_string())
+        }
+        Err(_) => body,
+    };
+    Response::from_parts(parts, Body::from(body))
+}
@@ -1,11 +1,27 @@
-use super::{ProviderAuthVec, ProviderFn};
-use crate::app_state::AppState;
+use super::{
+    params::{ParamRule, ParamValue},
+    ProviderAuthVec, ProviderFn,
+};
+use crate::{app_state::AppState, proxy::policy::ProxyPolicy};
 use axum::{body::Bytes, http::HeaderMap};
 use reqwest::{self as r, Url};
 use std::sync::Arc;
 
 const NVIDIA_MODELS_URL: &str = "https://integrate.api.nvidia.com/v1/models";
 const NVIDIA_CHAT_URL: &str = "https://integrate.api.nvidia.com/v1/chat/completions";
+const NVIDIA_COMPLETIONS_URL: &str = "https://integrate.api.nvidia.com/v1/completions";
+const NVIDIA_EMBEDDINGS_URL: &str = "https://integrate.api.nvidia.com/v1/embeddings";
+
+const PROXY_POLICY: ProxyPolicy = ProxyPolicy::Never;
+
+const PARAM_RULES: &[ParamRule] = &[
+    ParamRule::Rename("max_completion_tokens", "max_tokens"),
+    ParamRule::Drop("logit_bias"),
+    ParamRule::Clamp("n", 1.0, 1.0),
+    ParamRule::Clamp("temperature", 0.0, 1.0),
+    // NIM defaults to 1024 tokens, which truncates most replies
+    ParamRule::Default("max_tokens", ParamValue::Int(4096)),
+];
 
 pub struct NvidiaProvider {
     pub auth_vec: ProviderAuthVec,
@@ -25,10 +41,26 @@ impl ProviderFn for NvidiaProvider {
         Url::parse(NVIDIA_MODELS_URL).unwrap()
     }
 
-    fn chat_url(&self) -> Url {
+    fn chat_url(&self, _body: &Bytes) -> Url {
         Url::parse(NVIDIA_CHAT_URL).unwrap()
     }
 
+    fn completions_url(&self) -> Option<Url> {
+        Some(Url::parse(NVIDIA_COMPLETIONS_URL).unwrap())
+    }
+
+    fn embeddings_url(&self) -> Option<Url> {
+        Some(Url::parse(NVIDIA_EMBEDDINGS_URL).unwrap())
+    }
+
+    fn param_rules(&self) -> &'static [ParamRule] {
+        PARAM_RULES
+    }
+
+    fn proxy_policy(&self) -> ProxyPolicy {
+        PROXY_POLICY
+    }
+
     fn get_header_modifier(&self, headers: &mut HeaderMap) {
         headers.clear();
     }
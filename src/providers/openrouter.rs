@@ -1,6 +1,7 @@
 use crate::app_state::AppState;
 
 use super::{Provider, ProviderAuthVec, ProviderFn};
+use crate::proxy::policy::ProxyPolicy;
 use axum::{body::Bytes, http::HeaderMap};
 use chrono::{DateTime, Utc};
 use reqwest::{self as r, Url};
@@ -8,6 +9,10 @@ use std::sync::{Arc, Mutex};
 
 const OPENROUTER_MODELS_URL: &str = "https://openrouter.ai/api/v1/models";
 const OPENROUTER_CHAT_URL: &str = "https://openrouter.ai/api/v1/chat/completions";
+const OPENROUTER_COMPLETIONS_URL: &str = "https://openrouter.ai/api/v1/completions";
+const OPENROUTER_RESPONSES_URL: &str = "https://openrouter.ai/api/v1/responses";
+
+const PROXY_POLICY: ProxyPolicy = ProxyPolicy::FallbackToDirectOnProxyError;
 
 const RESET_TIME: chrono::NaiveTime = chrono::NaiveTime::from_hms_opt(0, 0, 0).unwrap();
 
@@ -32,10 +37,22 @@ impl ProviderFn for OpenRouterProvider {
         Url::parse(OPENROUTER_MODELS_URL).unwrap()
     }
 
-    fn chat_url(&self) -> Url {
+    fn chat_url(&self, _body: &Bytes) -> Url {
         Url::parse(OPENROUTER_CHAT_URL).unwrap()
     }
 
+    fn completions_url(&self) -> Option<Url> {
+        Some(Url::parse(OPENROUTER_COMPLETIONS_URL).unwrap())
+    }
+
+    fn responses_url(&self) -> Option<Url> {
+        Some(Url::parse(OPENROUTER_RESPONSES_URL).unwrap())
+    }
+
+    fn proxy_policy(&self) -> ProxyPolicy {
+        PROXY_POLICY
+    }
+
     fn get_header_modifier(&self, headers: &mut HeaderMap) {
         headers.clear();
     }
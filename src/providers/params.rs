@@ -0,0 +1,137 @@
+use serde_json::{Map, Value};
+
+/// A declarative rule normalizing a chat completions request parameter for an upstream.
+#[derive(Debug, Clone, Copy)]
+pub enum ParamRule {
+    /// Removes the parameter
+    Drop(&'static str),
+    /// Moves the parameter to another name, unless the other name is already set
+    Rename(&'static str, &'static str),
+    /// Clamps a numeric parameter into `min..=max`
+    Clamp(&'static str, f64, f64),
+    /// Sets the parameter to a value if it is missing
+    Default(&'static str, ParamValue),
+}
+
+/// A default value of a parameter
+#[derive(Debug, Clone, Copy)]
+pub enum ParamValue {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Str(&'static str),
+}
+
+impl From<ParamValue> for Value {
+    fn from(value: ParamValue) -> Self {
+        match value {
+            ParamValue::Int(n) => Value::from(n),
+            ParamValue::Float(n) => Value::from(n),
+            ParamValue::Bool(b) => Value::from(b),
+            ParamValue::Str(s) => Value::from(s),
+        }
+    }
+}
+
+impl ParamRule {
+    fn apply(&self, body: &mut Map<String, Value>) {
+        match *self {
+            ParamRule::Drop(key) => {
+                body.remove(key);
+            }
+            ParamRule::Rename(from, to) => {
+                if let Some(value) = body.remove(from) {
+                    body.entry(to).or_insert(value);
+                }
+            }
+            ParamRule::Clamp(key, min, max) => {
+                let Some(value) = body.get_mut(key) else {
+                    return;
+                };
+                if let Some(n) = value.as_i64() {
+                    *value = Value::from((n as f64).clamp(min, max) as i64);
+                } else if let Some(n) = value.as_f64() {
+                    *value = Value::from(n.clamp(min, max));
+                }
+            }
+            ParamRule::Default(key, value) => {
+                body.entry(key).or_insert_with(|| value.into());
+            }
+        }
+    }
+}
+
+/// Applies the rules to a JSON request body.
+/// Bodies that are not JSON objects, or untouched by the rules, are returned as is.
+pub fn normalize_params(rules: &[ParamRule], body: axum::body::Bytes) -> axum::body::Bytes {
+    if rules.is_empty() {
+        return body;
+    }
+    let Ok(Value::Object(mut json)) = serde_json::from_slice::<Value>(&body) else {
+        return body;
+    };
+    let original = json.clone();
+    for rule in rules {
+        rule.apply(&mut json);
+    }
+    if json == original {
+        return body;
+    }
+    axum::body::Bytes::from(Value::Object(json).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn normalize(rules: &[ParamRule], body: Value) -> Value {
+        let body = normalize_params(rules, body.to_string().into());
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[test]
+    fn drops_params() {
+        let rules = [ParamRule::Drop("frequency_penalty")];
+        let body = normalize(&rules, json!({ "frequency_penalty": 1, "n": 1 }));
+        assert_eq!(body, json!({ "n": 1 }));
+        assert_eq!(normalize(&rules, json!({ "n": 1 })), json!({ "n": 1 }));
+    }
+
+    #[test]
+    fn renames_params_without_overwriting() {
+        let rules = [ParamRule::Rename("max_completion_tokens", "max_tokens")];
+        let body = normalize(&rules, json!({ "max_completion_tokens": 10 }));
+        assert_eq!(body, json!({ "max_tokens": 10 }));
+        // the value already under the new name wins, the old name is still removed
+        let body = normalize(
+            &rules,
+            json!({ "max_completion_tokens": 10, "max_tokens": 20 }),
+        );
+        assert_eq!(body, json!({ "max_tokens": 20 }));
+    }
+
+    #[test]
+    fn clamps_numbers_only() {
+        let rules = [
+            ParamRule::Clamp("n", 1.0, 1.0),
+            ParamRule::Clamp("temperature", 0.0, 1.0),
+        ];
+        let body = normalize(&rules, json!({ "n": 4, "temperature": 1.5 }));
+        assert_eq!(body, json!({ "n": 1, "temperature": 1.0 }));
+        let body = normalize(&rules, json!({ "n": 0, "temperature": -0.5 }));
+        assert_eq!(body, json!({ "n": 1, "temperature": 0.0 }));
+        let body = normalize(&rules, json!({ "n": "4", "temperature": null }));
+        assert_eq!(body, json!({ "n": "4", "temperature": null }));
+    }
+
+    #[test]
+    fn sets_typed_defaults_only_when_missing() {
+        let rules = [
+            ParamRule::Default("max_tokens", ParamValue::Int(4096)),
+            ParamRule::Default("top_p", ParamValue::Float(0.5)),
+        ];
+        let body = normalize(&rules, json!({ "top_p": 1 }));
+        assert_eq!(body, json!({ "max_tokens": 4096, "top_p": 1 }));
+    }
+}
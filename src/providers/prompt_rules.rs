@@ -0,0 +1,144 @@
+use crate::{
+    app_state::AppState,
+    db::{prompt_rule::PromptRule, StorageFn as _},
+    middlewares::Caller,
+    utils::pattern::glob_match,
+};
+use eyre::Result;
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+/// Loads the prompt rules from the database, replacing the ones in memory.
+pub async fn load_prompt_rules(app: &Arc<AppState>) -> Result<usize> {
+    let rules = app.storage.load_prompt_rules().await?;
+    let count = rules.len();
+    *app.prompt_rules.lock().await = rules;
+    Ok(count)
+}
+
+pub async fn init_prompt_rules(app: &Arc<AppState>) {
+    match load_prompt_rules(app).await {
+        Ok(count) => tracing::info!("[Prompt] {} prompt rules initialized", count),
+        Err(e) => panic!("Failed to initialize prompt rules from database: {}", e),
+    }
+}
+
+/// Fills `{{provider}}`, `{{model}}`, `{{client}}` and `{{date}}` of a template.
+fn render(template: &str, provider_name: &str, model: &str, caller: &Caller) -> String {
+    template
+        .replace("{{provider}}", provider_name)
+        .replace("{{model}}", model)
+        .replace("{{client}}", caller.client.as_deref().unwrap_or_default())
+        .replace(
+            "{{date}}",
+            &chrono::Utc::now().format("%Y-%m-%d").to_string(),
+        )
+}
+
+/// Wraps the content of a user message with the parts of a template around `{{content}}`.
+fn wrap_content(message: &mut Value, template: &str) {
+    let (before, after) = template.split_once("{{content}}").unwrap_or((template, ""));
+    match &mut message["content"] {
+        Value::String(text) => *text = format!("{}{}{}", before, text, after),
+        Value::Array(parts) => {
+            if !before.is_empty() {
+                parts.insert(0, json!({ "type": "text", "text": before }));
+            }
+            if !after.is_empty() {
+                parts.push(json!({ "type": "text", "text": after }));
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Applies the prompt rules matching the provider, model and caller to a chat completions body,
+/// returning whether the body was changed.
+pub async fn apply_prompt_rules(
+    app: &Arc<AppState>,
+    provider_name: &str,
+    caller: &Caller,
+    body: Option<&mut Value>,
+) -> bool {
+    let Some(body) = body else {
+        return false;
+    };
+    let model = body["model"].as_str().unwrap_or_default().to_owned();
+    let rules = app
+        .prompt_rules
+        .lock()
+        .await
+        .iter()
+        .filter(|rule| rule.provider.as_ref().is_none_or(|p| p == provider_name))
+        .filter(|rule| rule.model.as_ref().is_none_or(|p| glob_match(p, &model)))
+        .filter(|rule| rule.client.is_none() || rule.client == caller.client)
+        .cloned()
+        .collect::<Vec<PromptRule>>();
+    let Some(messages) = body["messages"].as_array_mut() else {
+        return false;
+    };
+
+    let rules = rules
+        .iter()
+        .map(|rule| {
+            let content = render(&rule.content, provider_name, &model, caller);
+            (rule.action.as_str(), content)
+        })
+        .collect::<Vec<_>>();
+    apply_rules(messages, &rules)
+}
+
+/// Applies rendered `(action, content)` rules to the messages in order,
+/// returning whether the messages were changed.
+fn apply_rules(messages: &mut Vec<Value>, rules: &[(&str, String)]) -> bool {
+    let mut changed = false;
+    // prepended messages keep the rule order
+    let mut prepended = 0;
+    for (action, content) in rules {
+        match *action {
+            "prepend_system" => {
+                messages.insert(prepended, json!({ "role": "system", "content": content }));
+                prepended += 1;
+            }
+            "append_system" => messages.push(json!({ "role": "system", "content": content })),
+            // only the latest user message, earlier ones are sent as the client sent them
+            "wrap_user" => match messages.iter_mut().rev().find(|m| m["role"] == "user") {
+                Some(message) => wrap_content(message, content),
+                None => continue,
+            },
+            action => {
+                tracing::warn!("Unknown prompt rule action: {}", action);
+                continue;
+            }
+        }
+        changed = true;
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prepends_in_rule_order_and_wraps_latest_user_message() {
+        let mut messages = vec![
+            json!({ "role": "system", "content": "client" }),
+            json!({ "role": "user", "content": "a" }),
+            json!({ "role": "assistant", "content": "b" }),
+            json!({ "role": "user", "content": "c" }),
+        ];
+        let rules = [
+            ("prepend_system", "1".to_owned()),
+            ("prepend_system", "2".to_owned()),
+            ("append_system", "3".to_owned()),
+            ("wrap_user", "<{{content}}>".to_owned()),
+        ];
+        assert!(apply_rules(&mut messages, &rules));
+        let contents = messages
+            .iter()
+            .map(|m| m["content"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(contents, ["1", "2", "client", "a", "b", "<c>", "3"]);
+    }
+}
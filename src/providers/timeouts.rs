@@ -0,0 +1,19 @@
+use std::time::Duration;
+
+/// Deadlines of requests to a provider.
+#[derive(Debug, Clone, Copy)]
+pub struct Timeouts {
+    /// Connecting to the provider, or to the proxy
+    pub connect: Duration,
+    /// Receiving the response headers, which for non-streaming requests
+    /// only arrive once the whole response is generated
+    pub first_byte: Duration,
+    /// Waiting for the next chunk of the response body
+    pub idle: Duration,
+}
+
+pub const DEFAULT_TIMEOUTS: Timeouts = Timeouts {
+    connect: Duration::from_secs(10),
+    first_byte: Duration::from_secs(180),
+    idle: Duration::from_secs(120),
+};
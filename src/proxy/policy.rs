@@ -0,0 +1,60 @@
+use std::{fmt::Display, str::FromStr};
+
+/// Decides whether a request goes to the provider directly or through a proxy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProxyPolicy {
+    /// Always connect directly
+    Never,
+    /// Always go through a proxy
+    Always,
+    /// Connect directly, retry through a proxy if rate limited
+    FallbackToProxyOn429,
+    /// Go through a proxy, retry directly if the proxy fails
+    FallbackToDirectOnProxyError,
+}
+
+impl ProxyPolicy {
+    /// Parses the `proxy_flag` path segment: `x` no proxy, `o` proxy on.
+    pub fn from_flag(flag: &str) -> eyre::Result<Self> {
+        match flag {
+            "x" => Ok(Self::Never),
+            "o" => Ok(Self::Always),
+            _ => Err(eyre::eyre!("Invalid flag: {}", flag)),
+        }
+    }
+
+    /// The policy without retries, for requests that can only be sent once.
+    pub fn single_attempt(self) -> Self {
+        match self {
+            Self::FallbackToProxyOn429 => Self::Never,
+            Self::FallbackToDirectOnProxyError => Self::Always,
+            policy => policy,
+        }
+    }
+}
+
+impl FromStr for ProxyPolicy {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "never" => Ok(Self::Never),
+            "always" => Ok(Self::Always),
+            "proxy_on_429" => Ok(Self::FallbackToProxyOn429),
+            "direct_on_error" => Ok(Self::FallbackToDirectOnProxyError),
+            _ => Err(eyre::eyre!("Invalid proxy policy: {}", s)),
+        }
+    }
+}
+
+impl Display for ProxyPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Self::Never => "never",
+            Self::Always => "always",
+            Self::FallbackToProxyOn429 => "proxy_on_429",
+            Self::FallbackToDirectOnProxyError => "direct_on_error",
+        };
+        write!(f, "{}", s)
+    }
+}
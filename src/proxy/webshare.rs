@@ -1,9 +1,10 @@
 use crate::app_state::AppState;
-use crate::db::proxy::{db_load_proxies, db_save_proxies};
+use crate::db::StorageFn as _;
 use axum::http::HeaderMap;
 use eyre::Result;
 use rand::Rng;
 use reqwest as r;
+use sha2::{Digest, Sha256};
 use std::time::Duration;
 use std::{fmt::Display, sync::Arc};
 use tokio::time::Instant;
@@ -19,6 +20,13 @@ pub struct Proxy {
     pub password: String,
 }
 
+impl Proxy {
+    /// Whether both point to the same proxy server.
+    pub fn same_as(&self, other: &Proxy) -> bool {
+        self.proxy_address == other.proxy_address && self.port == other.port
+    }
+}
+
 impl Display for Proxy {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
@@ -69,7 +77,7 @@ async fn get_proxies(app: &Arc<AppState>) -> eyre::Result<Vec<Arc<Proxy>>> {
 
 pub async fn update_proxies(app: &Arc<AppState>) -> Result<()> {
     let new_proxies = get_proxies(app).await?;
-    db_save_proxies(&app.pool, &new_proxies).await?;
+    app.storage.save_proxies(&new_proxies).await?;
 
     let mut proxies = app.proxies.lock().await;
     *proxies = new_proxies;
@@ -78,7 +86,7 @@ pub async fn update_proxies(app: &Arc<AppState>) -> Result<()> {
 }
 
 pub async fn init_proxies(app: &Arc<AppState>) {
-    match db_load_proxies(&app.pool).await {
+    match app.storage.load_proxies().await {
         Ok(db_proxies) if !db_proxies.is_empty() => {
             let mut proxies = app.proxies.lock().await;
             *proxies = db_proxies;
@@ -110,22 +118,75 @@ pub fn update_proxies_debounced(app: &Arc<AppState>) {
     });
 }
 
-pub async fn pick_proxy(app: &Arc<AppState>) -> Option<Arc<Proxy>> {
+pub async fn pick_proxy(app: &Arc<AppState>, except: Option<&Proxy>) -> Option<Arc<Proxy>> {
     let proxies = app.proxies.lock().await;
+    let proxies = candidates(&proxies, except);
     if proxies.is_empty() {
         return None;
     }
     let size = proxies.len();
     let mut rng = app.rng.lock().await;
     let i = rng.random_range(0..size);
-    proxies.get(i).cloned()
+    proxies.get(i).map(|proxy| (*proxy).clone())
+}
+
+/// Picks the proxy bound to an auth key.
+///
+/// Uses rendezvous hashing over the proxy pool, so a key keeps egressing from the
+/// same proxy, and only the keys bound to a removed proxy move to another one.
+/// If its proxy is `except`, the key moves to its next proxy in the same order.
+pub async fn pick_proxy_for_auth(
+    app: &Arc<AppState>,
+    auth_id: i32,
+    except: Option<&Proxy>,
+) -> Option<Arc<Proxy>> {
+    let proxies = app.proxies.lock().await;
+    candidates(&proxies, except)
+        .into_iter()
+        .max_by_key(|proxy| rendezvous_weight(auth_id, proxy))
+        .cloned()
+}
+
+/// The proxies to pick from, without `except` unless it is the only one left.
+fn candidates<'a>(proxies: &'a [Arc<Proxy>], except: Option<&Proxy>) -> Vec<&'a Arc<Proxy>> {
+    let others: Vec<_> = proxies
+        .iter()
+        .filter(|proxy| except.is_none_or(|except| !proxy.same_as(except)))
+        .collect();
+    match others.is_empty() {
+        true => proxies.iter().collect(),
+        false => others,
+    }
+}
+
+/// The weight of a proxy for an auth key, stable across builds and replicas
+/// (unlike `DefaultHasher`), so keys keep their proxy after a toolchain upgrade.
+fn rendezvous_weight(auth_id: i32, proxy: &Proxy) -> u64 {
+    let mut hasher = Sha256::new();
+    hasher.update(auth_id.to_be_bytes());
+    hasher.update(proxy.proxy_address.as_bytes());
+    hasher.update(proxy.port.to_be_bytes());
+    let digest = hasher.finalize();
+    u64::from_be_bytes(digest[..8].try_into().unwrap())
 }
 
-pub async fn create_proxied_client(app: &Arc<AppState>) -> Result<(r::Client, Option<Arc<Proxy>>)> {
+/// Creates a client going through a proxy.
+/// If an auth key is given, its bound proxy is used, otherwise a random one.
+/// `except` is avoided as long as there is another proxy.
+pub async fn create_proxied_client(
+    app: &Arc<AppState>,
+    auth_id: Option<i32>,
+    except: Option<&Proxy>,
+    connect_timeout: Duration,
+) -> Result<(r::Client, Option<Arc<Proxy>>)> {
     update_proxies_debounced(app);
-    match pick_proxy(app).await {
+    let proxy = match auth_id {
+        Some(auth_id) => pick_proxy_for_auth(app, auth_id, except).await,
+        None => pick_proxy(app, except).await,
+    };
+    match proxy {
         Some(proxy) => {
-            let client = r::Client::builder();
+            let client = r::Client::builder().connect_timeout(connect_timeout);
             let req_proxy = r::Proxy::all(proxy.to_string())?;
             Ok((client.proxy(req_proxy.clone()).build()?, Some(proxy)))
         }
@@ -0,0 +1,44 @@
+use crate::{
+    app_state::AppState,
+    middlewares::Caller,
+    routes::{proxied_chat::send_chat, ProviderPath},
+    translate::anthropic::{error_response, translate_response, MessagesRequest},
+};
+use axum::{
+    body::{Body, Bytes},
+    extract::{Extension, Path, State},
+    http::{HeaderMap, Response, StatusCode},
+};
+use std::sync::Arc;
+
+/// Anthropic Messages API, translated to chat completions of the provider.
+pub async fn anthropic_messages(
+    State(app): State<Arc<AppState>>,
+    Path(ProviderPath {
+        proxy_flag,
+        provider_name,
+    }): Path<ProviderPath>,
+    Extension(caller): Extension<Caller>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response<Body> {
+    let request = match serde_json::from_slice::<MessagesRequest>(&body) {
+        Ok(request) => request,
+        Err(e) => {
+            tracing::warn!("Error parsing messages body: {}", e);
+            return error_response(StatusCode::BAD_REQUEST, &e.to_string());
+        }
+    };
+
+    let chat_body = Bytes::from(request.to_chat_body().to_string());
+    let res = send_chat(
+        &app,
+        &provider_name,
+        proxy_flag.as_deref(),
+        &caller,
+        headers,
+        chat_body,
+    )
+    .await;
+    translate_response(res, &request.model).await
+}
@@ -1,6 +1,6 @@
 use crate::{
     app_state::AppState,
-    db::auth::ProviderAuth,
+    db::{auth::ProviderAuth, StorageFn as _},
     providers::{auth::sync_auth, ProviderFn as _},
 };
 use axum::{extract::State, http::StatusCode, response::IntoResponse};
@@ -18,10 +18,7 @@ pub async fn sync_auth_route(State(app): State<Arc<AppState>>) -> impl IntoRespo
 }
 
 pub async fn pull_auth_route(State(app): State<Arc<AppState>>) -> impl IntoResponse {
-    let all_auth: Vec<ProviderAuth> = sqlx::query_as("SELECT * FROM auth")
-        .fetch_all(&app.pool)
-        .await
-        .unwrap();
+    let all_auth: Vec<ProviderAuth> = app.storage.get_all_auth().await.unwrap();
 
     let providers = app.providers.lock().await;
 
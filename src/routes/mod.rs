@@ -1,33 +1,34 @@
+mod anthropic_messages;
 pub mod auth_management;
 mod health;
+pub mod model_access;
+pub mod model_aliases;
+pub mod prompt_rules;
+mod proxied_audio;
 mod proxied_chat;
+mod proxied_completions;
+mod proxied_embeddings;
+mod proxied_images;
 mod proxied_models;
+mod proxied_responses;
 mod show_chat;
+mod upstream;
 
+pub use anthropic_messages::anthropic_messages;
 pub use health::health;
+pub use proxied_audio::{proxied_speech, proxied_transcriptions};
 pub use proxied_chat::proxied_chat;
+pub use proxied_completions::proxied_completions;
+pub use proxied_embeddings::proxied_embeddings;
+pub use proxied_images::proxied_images;
 pub use proxied_models::proxied_models;
+pub use proxied_responses::proxied_responses;
 pub use show_chat::toggle_show_chat;
 
-use crate::{
-    app_state::AppState,
-    proxy::webshare::{create_proxied_client, Proxy},
-};
-use eyre::Result;
-use reqwest::Client;
-use std::sync::Arc;
-
-pub async fn handle_proxy_flag(
-    app: &Arc<AppState>,
-    flag: &str,
-) -> Result<(Client, Option<Arc<Proxy>>)> {
-    let (client, proxy) = match flag {
-        "x" => (Client::builder().build()?, None),
-        "o" => match create_proxied_client(app).await {
-            Ok(client) => client,
-            Err(e) => return Err(e),
-        },
-        _ => return Err(eyre::eyre!("Invalid flag")),
-    };
-    Ok((client, proxy))
+/// Path parameters of the proxied routes.
+/// `proxy_flag` is optional, overriding the provider's proxy policy when present.
+#[derive(serde::Deserialize)]
+pub struct ProviderPath {
+    pub proxy_flag: Option<String>,
+    pub provider_name: String,
 }
@@ -0,0 +1,19 @@
+use crate::{app_state::AppState, providers::model_access::load_model_access};
+use axum::{extract::State, http::StatusCode, response::IntoResponse};
+use std::sync::Arc;
+
+pub async fn pull_model_access_route(State(app): State<Arc<AppState>>) -> impl IntoResponse {
+    match load_model_access(&app).await {
+        Ok((clients, rules)) => {
+            tracing::info!("Pulled {} clients, {} model rules", clients, rules);
+            (StatusCode::OK, "OK")
+        }
+        Err(e) => {
+            tracing::error!("pull_model_access error: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to pull model access",
+            )
+        }
+    }
+}
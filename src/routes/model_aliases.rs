@@ -0,0 +1,19 @@
+use crate::{app_state::AppState, providers::model_alias::load_model_aliases};
+use axum::{extract::State, http::StatusCode, response::IntoResponse};
+use std::sync::Arc;
+
+pub async fn pull_model_aliases_route(State(app): State<Arc<AppState>>) -> impl IntoResponse {
+    match load_model_aliases(&app).await {
+        Ok(count) => {
+            tracing::info!("Pulled {} model aliases", count);
+            (StatusCode::OK, "OK")
+        }
+        Err(e) => {
+            tracing::error!("pull_model_aliases error: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to pull model aliases",
+            )
+        }
+    }
+}
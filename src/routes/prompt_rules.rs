@@ -0,0 +1,19 @@
+use crate::{app_state::AppState, providers::prompt_rules::load_prompt_rules};
+use axum::{extract::State, http::StatusCode, response::IntoResponse};
+use std::sync::Arc;
+
+pub async fn pull_prompt_rules_route(State(app): State<Arc<AppState>>) -> impl IntoResponse {
+    match load_prompt_rules(&app).await {
+        Ok(count) => {
+            tracing::info!("Pulled {} prompt rules", count);
+            (StatusCode::OK, "OK")
+        }
+        Err(e) => {
+            tracing::error!("pull_prompt_rules error: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to pull prompt rules",
+            )
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use crate::{
+    app_state::AppState,
+    middlewares::Caller,
+    providers::ProviderFn as _,
+    routes::{
+        upstream::{forward_post, Endpoint, ForwardBody},
+        ProviderPath,
+    },
+};
+use axum::{
+    body::{Body, Bytes},
+    extract::{Extension, Path, State},
+    http::{HeaderMap, Response},
+};
+use std::sync::Arc;
+
+const AUDIO_TRANSCRIPTIONS: Endpoint = Endpoint {
+    name: "audio transcriptions",
+    url: |provider| provider.audio_transcriptions_url(),
+    cost: |_, _| 1,
+};
+
+const AUDIO_SPEECH: Endpoint = Endpoint {
+    name: "audio speech",
+    url: |provider| provider.audio_speech_url(),
+    cost: |_, _| 1,
+};
+
+/// Multipart audio uploads are streamed to the provider without buffering.
+pub async fn proxied_transcriptions(
+    State(app): State<Arc<AppState>>,
+    Path(ProviderPath {
+        proxy_flag,
+        provider_name,
+    }): Path<ProviderPath>,
+    Extension(caller): Extension<Caller>,
+    headers: HeaderMap,
+    body: Body,
+) -> Response<Body> {
+    forward_post(
+        &app,
+        &provider_name,
+        proxy_flag.as_deref(),
+        &caller,
+        &AUDIO_TRANSCRIPTIONS,
+        headers,
+        ForwardBody::Stream(body),
+    )
+    .await
+}
+
+pub async fn proxied_speech(
+    State(app): State<Arc<AppState>>,
+    Path(ProviderPath {
+        proxy_flag,
+        provider_name,
+    }): Path<ProviderPath>,
+    Extension(caller): Extension<Caller>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response<Body> {
+    forward_post(
+        &app,
+        &provider_name,
+        proxy_flag.as_deref(),
+        &caller,
+        &AUDIO_SPEECH,
+        headers,
+        ForwardBody::Json(body),
+    )
+    .await
+}
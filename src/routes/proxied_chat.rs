@@ -1,45 +1,80 @@
 use crate::{
     app_state::AppState,
-    providers::{auth::update_auth_state_on_response, ProviderFn},
+    cache::{
+        cache_response, cached_response, get_cached_response, request_hash, set_cache_status,
+        CacheKey, CACHE_HEADER,
+    },
+    coalesce::{follow_flight, join_flight, FlightRole},
+    db::model_alias::ModelAlias,
+    middlewares::Caller,
+    providers::{
+        auth::update_auth_state_on_response,
+        model_access::{model_forbidden, ModelAccess},
+        model_alias::{resolve_model_alias, rewrite_response_model},
+        params::normalize_params,
+        prompt_rules::apply_prompt_rules,
+        ProviderFn,
+    },
     proxy::webshare::disable_failed_proxy,
-    routes::handle_proxy_flag,
-    utils::data_types::ChatBody,
+    routes::{
+        upstream::{record_upstream_failure, resolve_proxy_policy, send_hedged, Failure},
+        ProviderPath,
+    },
+    utils::{
+        aggregate::aggregate_stream,
+        keep_alive::keep_alive,
+        sse::{is_event_stream, synthesize_stream},
+        stream_guard::{guard_stream, Interruption},
+    },
 };
 use axum::{
     body::{Body, Bytes},
-    extract::{Path, State},
-    http::{HeaderMap, Response, StatusCode},
+    extract::{Extension, Path, State},
+    http::{header, HeaderMap, Response, StatusCode},
     response::IntoResponse,
 };
+use serde_json::{json, Value};
 use std::sync::Arc;
 
 pub async fn proxied_chat(
     State(app): State<Arc<AppState>>,
-    Path((proxy_flag, provider_name)): Path<(String, String)>,
-    mut headers: HeaderMap,
+    Path(ProviderPath {
+        proxy_flag,
+        provider_name,
+    }): Path<ProviderPath>,
+    Extension(caller): Extension<Caller>,
+    headers: HeaderMap,
     body: Bytes,
 ) -> Response<Body> {
-    let body_str = String::from_utf8_lossy(&body);
-    let chat_body: Option<ChatBody> = serde_json::from_str(&body_str).ok();
-    let model = chat_body.map(|b| b.model);
+    send_chat(
+        &app,
+        &provider_name,
+        proxy_flag.as_deref(),
+        &caller,
+        headers,
+        body,
+    )
+    .await
+}
 
-    tracing::info!(
-        "[POST] {} {} - {}",
-        proxy_flag,
-        provider_name,
-        model.unwrap_or("".to_string())
-    );
-
-    let (client, proxy) = match handle_proxy_flag(&app, &proxy_flag).await {
-        Ok(result) => result,
-        Err(e) => {
-            let msg = format!("Failed to create reqwest client: {}", e);
-            tracing::error!("{}", msg);
-            return (StatusCode::INTERNAL_SERVER_ERROR, msg).into_response();
-        }
-    };
+/// Sends an OpenAI chat completions request to the provider.
+pub async fn send_chat(
+    app: &Arc<AppState>,
+    provider_name: &str,
+    proxy_flag: Option<&str>,
+    caller: &Caller,
+    mut headers: HeaderMap,
+    body: Bytes,
+) -> Response<Body> {
+    let mut chat_body: Option<Value> = serde_json::from_slice(&body).ok();
+    let alias = resolve_model_alias(app, provider_name, chat_body.as_mut()).await;
+    let model = chat_body
+        .as_ref()
+        .and_then(|b| b["model"].as_str())
+        .map(str::to_owned);
+    let wants_stream = chat_body.as_ref().is_some_and(|b| b["stream"] == true);
 
-    let provider = match app.get_provider(&provider_name).await {
+    let provider = match app.get_provider(provider_name).await {
         Some(provider) => provider,
         None => {
             let msg = format!("Provider not found: {}", provider_name);
@@ -48,41 +83,181 @@ pub async fn proxied_chat(
         }
     };
 
-    provider.post_header_modifier(&mut headers);
-    let auth = provider.apply_auth(&mut headers);
+    let policy = match resolve_proxy_policy(app, provider_name, &provider, proxy_flag) {
+        Ok(policy) => policy,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+
+    let model = model.unwrap_or_default();
+    tracing::info!("[POST] {} {} - {}", policy, provider_name, model);
 
+    if !ModelAccess::new(app, provider_name, caller)
+        .await
+        .allows(&model)
     {
-        let show_chat = *app.show_chat.lock().await;
-        if show_chat {
-            tracing::info!("Body: {}", String::from_utf8_lossy(&body));
+        return model_forbidden(&model);
+    }
+
+    // the upstream cannot stream this model, request a full response and stream it ourselves
+    let streams = provider.supports_streaming(&model)
+        && !app
+            .env
+            .non_streaming_models
+            .contains(&(provider_name.to_lowercase(), model.clone()));
+    let force_non_stream = wants_stream && !streams;
+    let prompted = apply_prompt_rules(app, provider_name, caller, chat_body.as_mut()).await;
+    let body = match chat_body {
+        Some(Value::Object(mut chat)) if alias.is_some() || prompted || force_non_stream => {
+            if force_non_stream {
+                chat.insert("stream".to_owned(), Value::Bool(false));
+                chat.remove("stream_options");
+            }
+            Bytes::from(Value::Object(chat).to_string())
+        }
+        _ => body,
+    };
+
+    let body = normalize_params(provider.param_rules(), body);
+
+    let cache_key = CacheKey::new(app, provider_name, &headers, &body);
+    headers.remove(CACHE_HEADER);
+    if let Some(key) = &cache_key {
+        if let Some(response) = get_cached_response(app, key).await {
+            tracing::info!("[Cache] hit {} - {}", provider_name, model);
+            let mut res = finish_response(cached_response(response), alias, wants_stream).await;
+            set_cache_status(&mut res, true);
+            return res;
         }
     }
 
-    let res = client
-        .post(provider.chat_url())
-        .body(provider.body_modifier(body.clone()))
-        .headers(headers.clone())
-        .send()
+    let keep_alive_interval = app.env.keep_alive_interval.filter(|_| wants_stream);
+    let leader = match flight_key(app, provider_name, &body, &alias, wants_stream) {
+        Some(key) => match join_flight(app, key).await {
+            FlightRole::Leader(leader) => Some(leader),
+            FlightRole::Follower(flight) => {
+                tracing::info!("[Coalesce] {} - {}", provider_name, model);
+                return match keep_alive_interval {
+                    Some(interval) => keep_alive(follow_flight(flight), interval).await,
+                    None => follow_flight(flight).await,
+                };
+            }
+        },
+        None => None,
+    };
+
+    let app = app.clone();
+    let provider_name = provider_name.to_owned();
+    let upstream = async move {
+        provider.post_header_modifier(&mut headers);
+
+        {
+            let show_chat = *app.show_chat.lock().await;
+            if show_chat {
+                // the body as sent, after every modification
+                let sent = provider.body_modifier(body.clone());
+                let sent = sent.as_bytes().unwrap_or(&body);
+                tracing::info!("Body: {}", String::from_utf8_lossy(sent));
+            }
+        }
+
+        let attempt = send_hedged(
+            &app,
+            &provider_name,
+            &provider,
+            policy,
+            &headers,
+            |client, headers| {
+                client
+                    .post(provider.chat_url(&body))
+                    .body(provider.body_modifier(body.clone()))
+                    .headers(headers.clone())
+            },
+        )
         .await;
+        let (res, proxy, auth) = match attempt {
+            Ok(attempt) => (attempt.upstream.res, attempt.upstream.proxy, attempt.auth),
+            Err(res) => return res,
+        };
 
-    let res = match res {
-        Ok(res) => res,
-        Err(err) => {
+        let status = res.status();
+        update_auth_state_on_response(&app, &auth, &status, 1);
+        // only disable the proxy if no auth key was sent, neither picked nor the client's own
+        if status == StatusCode::TOO_MANY_REQUESTS
+            && auth.is_none()
+            && headers.get(header::AUTHORIZATION).is_none()
+        {
             disable_failed_proxy(&app, &proxy).await;
-            let msg = "Error sending request";
-            tracing::error!("{}: {} - {:?}", msg, err, proxy);
-            return (StatusCode::INTERNAL_SERVER_ERROR, msg).into_response();
+        }
+
+        let mut res = provider.get_response(body, res).await;
+        let cached = cache_key.is_some();
+        if let Some(key) = cache_key {
+            res = cache_response(&app, key, res).await;
+        }
+        let mut res = finish_response(res, alias, wants_stream).await;
+        if cached {
+            set_cache_status(&mut res, false);
+        }
+        guard_stream(res, move |interruption, msg| {
+            tracing::warn!("[Stream] {} - {}: {}", provider_name, model, msg);
+            // dropped bodies are already recorded when they fail
+            if interruption == Interruption::Truncated {
+                tokio::spawn(async move {
+                    record_upstream_failure(&app, Failure::Upstream, &proxy, &auth).await
+                });
+            }
+        })
+    };
+    let response = async move {
+        match leader {
+            Some(leader) => leader.lead(upstream).await,
+            None => upstream.await,
         }
     };
+    match keep_alive_interval {
+        Some(interval) => keep_alive(response, interval).await,
+        None => response.await,
+    }
+}
 
-    let status = res.status();
-    update_auth_state_on_response(&app, &auth, &status);
-    // only disable the proxy if there is no auth header
-    if status == StatusCode::TOO_MANY_REQUESTS
-        && headers.get(axum::http::header::AUTHORIZATION).is_none()
-    {
-        disable_failed_proxy(&app, &proxy).await;
+/// Key of concurrent identical requests sharing one upstream call,
+/// `None` if `COALESCE_REQUESTS` is off.
+fn flight_key(
+    app: &Arc<AppState>,
+    provider_name: &str,
+    body: &Bytes,
+    alias: &Option<ModelAlias>,
+    wants_stream: bool,
+) -> Option<String> {
+    if !app.env.coalesce_requests {
+        return None;
     }
+    let body = serde_json::from_slice::<Value>(body).ok()?;
+    // responses differ by their shape and rewritten model
+    let alias = alias.as_ref().filter(|alias| alias.rewrite_response);
+    let request = json!({
+        "body": body,
+        "stream": wants_stream,
+        "alias": alias.map(|alias| &alias.alias),
+    });
+    Some(request_hash(provider_name, &request))
+}
 
-    provider.get_response(body, res).await
+/// Shapes the provider's response for the client.
+async fn finish_response(
+    mut res: Response<Body>,
+    alias: Option<ModelAlias>,
+    wants_stream: bool,
+) -> Response<Body> {
+    if let Some(alias) = alias.filter(|alias| alias.rewrite_response) {
+        res = rewrite_response_model(res, alias.alias).await;
+    }
+    // some upstreams ignore `stream` and answer with a full response, or the other way round
+    if wants_stream && res.status().is_success() && !is_event_stream(res.headers()) {
+        return synthesize_stream(res).await;
+    }
+    if !wants_stream {
+        return aggregate_stream(res, false).await;
+    }
+    res
 }
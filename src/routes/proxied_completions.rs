@@ -0,0 +1,44 @@
+use crate::{
+    app_state::AppState,
+    middlewares::Caller,
+    providers::ProviderFn as _,
+    routes::{
+        upstream::{forward_post, Endpoint, ForwardBody},
+        ProviderPath,
+    },
+};
+use axum::{
+    body::{Body, Bytes},
+    extract::{Extension, Path, State},
+    http::{HeaderMap, Response},
+};
+use std::sync::Arc;
+
+const COMPLETIONS: Endpoint = Endpoint {
+    name: "completions",
+    url: |provider| provider.completions_url(),
+    cost: |_, _| 1,
+};
+
+/// Legacy text completions, for providers that still support them.
+pub async fn proxied_completions(
+    State(app): State<Arc<AppState>>,
+    Path(ProviderPath {
+        proxy_flag,
+        provider_name,
+    }): Path<ProviderPath>,
+    Extension(caller): Extension<Caller>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response<Body> {
+    forward_post(
+        &app,
+        &provider_name,
+        proxy_flag.as_deref(),
+        &caller,
+        &COMPLETIONS,
+        headers,
+        ForwardBody::Json(body),
+    )
+    .await
+}
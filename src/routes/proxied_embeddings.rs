@@ -0,0 +1,43 @@
+use crate::{
+    app_state::AppState,
+    middlewares::Caller,
+    providers::ProviderFn as _,
+    routes::{
+        upstream::{forward_post, Endpoint, ForwardBody},
+        ProviderPath,
+    },
+};
+use axum::{
+    body::{Body, Bytes},
+    extract::{Extension, Path, State},
+    http::{HeaderMap, Response},
+};
+use std::sync::Arc;
+
+const EMBEDDINGS: Endpoint = Endpoint {
+    name: "embeddings",
+    url: |provider| provider.embeddings_url(),
+    cost: |_, _| 1,
+};
+
+pub async fn proxied_embeddings(
+    State(app): State<Arc<AppState>>,
+    Path(ProviderPath {
+        proxy_flag,
+        provider_name,
+    }): Path<ProviderPath>,
+    Extension(caller): Extension<Caller>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response<Body> {
+    forward_post(
+        &app,
+        &provider_name,
+        proxy_flag.as_deref(),
+        &caller,
+        &EMBEDDINGS,
+        headers,
+        ForwardBody::Json(body),
+    )
+    .await
+}
@@ -0,0 +1,50 @@
+use crate::{
+    app_state::AppState,
+    middlewares::Caller,
+    providers::ProviderFn as _,
+    routes::{
+        upstream::{forward_post, Endpoint, ForwardBody},
+        ProviderPath,
+    },
+};
+use axum::{
+    body::{Body, Bytes},
+    extract::{Extension, Path, State},
+    http::{HeaderMap, Response},
+};
+use std::sync::Arc;
+
+const IMAGES: Endpoint = Endpoint {
+    name: "images",
+    url: |provider| provider.images_url(),
+    // each of the `n` images is charged
+    cost: |provider, body| {
+        let n = body["n"].as_i64().unwrap_or(1).clamp(1, MAX_IMAGES);
+        provider.image_cost().saturating_mul(n as i32)
+    },
+};
+
+/// Upper bound of the `n` images charged per request, as accepted by OpenAI
+const MAX_IMAGES: i64 = 10;
+
+pub async fn proxied_images(
+    State(app): State<Arc<AppState>>,
+    Path(ProviderPath {
+        proxy_flag,
+        provider_name,
+    }): Path<ProviderPath>,
+    Extension(caller): Extension<Caller>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response<Body> {
+    forward_post(
+        &app,
+        &provider_name,
+        proxy_flag.as_deref(),
+        &caller,
+        &IMAGES,
+        headers,
+        ForwardBody::Json(body),
+    )
+    .await
+}
@@ -1,31 +1,34 @@
 use crate::{
-    app_state::AppState, providers::ProviderFn, proxy::webshare::disable_failed_proxy,
-    routes::handle_proxy_flag, utils::stream_body::get_response_stream,
+    app_state::AppState,
+    middlewares::Caller,
+    providers::{
+        model_access::{filter_models, ModelAccess},
+        ProviderFn,
+    },
+    proxy::webshare::disable_failed_proxy,
+    routes::{
+        upstream::{resolve_proxy_policy, send_upstream, PickedProxy},
+        ProviderPath,
+    },
+    utils::stream_body::get_response_stream,
 };
 use axum::{
     body::Body,
-    extract::{Path, State},
-    http::{HeaderMap, Response, StatusCode},
+    extract::{Extension, Path, State},
+    http::{header, HeaderMap, Response, StatusCode},
     response::IntoResponse,
 };
 use std::sync::Arc;
 
 pub async fn proxied_models(
     State(app): State<Arc<AppState>>,
-    Path((proxy_flag, provider_name)): Path<(String, String)>,
+    Path(ProviderPath {
+        proxy_flag,
+        provider_name,
+    }): Path<ProviderPath>,
+    Extension(caller): Extension<Caller>,
     mut headers: HeaderMap,
 ) -> Response<Body> {
-    tracing::info!("[GET] {} {}", proxy_flag, provider_name);
-
-    let (client, proxy) = match handle_proxy_flag(&app, &proxy_flag).await {
-        Ok(result) => result,
-        Err(e) => {
-            let msg = format!("Failed to create reqwest client: {}", e);
-            tracing::error!("{}", msg);
-            return (StatusCode::INTERNAL_SERVER_ERROR, msg).into_response();
-        }
-    };
-
     let provider = match app.get_provider(&provider_name).await {
         Some(provider) => provider,
         None => {
@@ -34,32 +37,43 @@ pub async fn proxied_models(
         }
     };
 
-    provider.get_header_modifier(&mut headers);
-    let _auth = provider.apply_auth(&mut headers);
+    let policy = match resolve_proxy_policy(&app, &provider_name, &provider, proxy_flag.as_deref())
+    {
+        Ok(policy) => policy,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
 
-    let res = client
-        .get(provider.models_url())
-        .headers(headers.clone())
-        .send()
-        .await;
+    tracing::info!("[GET] {} {}", policy, provider_name);
 
-    let res = match res {
-        Ok(res) => res,
-        Err(err) => {
-            disable_failed_proxy(&app, &proxy).await;
-            let msg = "Error sending request";
-            tracing::error!("{}: {} - {:?}", msg, err, proxy);
-            return (StatusCode::INTERNAL_SERVER_ERROR, msg).into_response();
-        }
+    provider.get_header_modifier(&mut headers);
+    // listing models is free, so the key is neither leased nor charged
+    let auth = provider.pick_auth_except(None);
+    provider.apply_picked_auth(&mut headers, &auth);
+
+    let picked = PickedProxy::default();
+    let upstream = send_upstream(
+        &app,
+        policy,
+        provider.timeouts(),
+        &auth,
+        &picked,
+        |client| client.get(provider.models_url()).headers(headers.clone()),
+    )
+    .await;
+    let (res, proxy) = match upstream {
+        Ok(upstream) => (upstream.res, upstream.proxy),
+        Err(res) => return res,
     };
 
     let status = res.status();
-    // only disable the proxy if there is no auth header
+    // only disable the proxy if no auth key was sent, neither picked nor the client's own
     if status == StatusCode::TOO_MANY_REQUESTS
-        && headers.get(axum::http::header::AUTHORIZATION).is_none()
+        && auth.is_none()
+        && headers.get(header::AUTHORIZATION).is_none()
     {
         disable_failed_proxy(&app, &proxy).await;
     }
 
-    get_response_stream(res).await
+    let access = ModelAccess::new(&app, &provider_name, &caller).await;
+    filter_models(&access, get_response_stream(res).await).await
 }
@@ -0,0 +1,78 @@
+use crate::{
+    app_state::AppState,
+    middlewares::Caller,
+    providers::ProviderFn as _,
+    routes::{
+        proxied_chat::send_chat,
+        upstream::{forward_post, Endpoint, ForwardBody},
+        ProviderPath,
+    },
+    translate::responses::{to_chat_body, translate_response},
+};
+use axum::{
+    body::{Body, Bytes},
+    extract::{Extension, Path, State},
+    http::{HeaderMap, Response, StatusCode},
+    response::IntoResponse,
+};
+use std::sync::Arc;
+
+const RESPONSES: Endpoint = Endpoint {
+    name: "responses",
+    url: |provider| provider.responses_url(),
+    cost: |_, _| 1,
+};
+
+/// Responses API, forwarded as is to providers supporting it,
+/// otherwise translated to chat completions.
+pub async fn proxied_responses(
+    State(app): State<Arc<AppState>>,
+    Path(ProviderPath {
+        proxy_flag,
+        provider_name,
+    }): Path<ProviderPath>,
+    Extension(caller): Extension<Caller>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response<Body> {
+    let supported = app
+        .get_provider(&provider_name)
+        .await
+        .is_some_and(|provider| provider.responses_url().is_some());
+    if supported {
+        return forward_post(
+            &app,
+            &provider_name,
+            proxy_flag.as_deref(),
+            &caller,
+            &RESPONSES,
+            headers,
+            ForwardBody::Json(body),
+        )
+        .await;
+    }
+
+    let request = match serde_json::from_slice::<serde_json::Value>(&body) {
+        Ok(request) => request,
+        Err(e) => {
+            tracing::warn!("Error parsing responses body: {}", e);
+            return (StatusCode::BAD_REQUEST, e.to_string()).into_response();
+        }
+    };
+    if !request["previous_response_id"].is_null() {
+        let msg = "previous_response_id is not supported, responses are not stored";
+        return (StatusCode::BAD_REQUEST, msg).into_response();
+    }
+
+    let chat_body = Bytes::from(to_chat_body(&request).to_string());
+    let res = send_chat(
+        &app,
+        &provider_name,
+        proxy_flag.as_deref(),
+        &caller,
+        headers,
+        chat_body,
+    )
+    .await;
+    translate_response(res, &request).await
+}
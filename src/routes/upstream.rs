@@ -0,0 +1,461 @@
+use crate::{
+    app_state::AppState,
+    db::auth::ProviderAuth,
+    middlewares::Caller,
+    providers::{
+        auth::{record_auth_failure, refund_lease, update_auth_state_on_response},
+        model_access::{model_forbidden, unknown_model_forbidden, ModelAccess},
+        timeouts::Timeouts,
+        Provider, ProviderFn as _,
+    },
+    proxy::{
+        policy::ProxyPolicy,
+        webshare::{create_proxied_client, disable_failed_proxy, Proxy},
+    },
+    utils::stream_body::get_response_stream,
+};
+use axum::{
+    body::{Body, Bytes},
+    http::{header, HeaderMap, Response, StatusCode},
+    response::IntoResponse,
+};
+use eyre::Result;
+use futures::{
+    future::{select, Either},
+    FutureExt as _, StreamExt as _,
+};
+use reqwest::{Client, RequestBuilder, Url};
+use serde_json::Value;
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// A response received from the provider, along with the proxy it went through.
+pub struct Upstream {
+    pub res: reqwest::Response,
+    pub proxy: Option<Arc<Proxy>>,
+}
+
+/// Resolves the proxy policy of a request.
+///
+/// The `proxy_flag` path segment overrides `PROXY_POLICY`,
+/// which overrides the provider's default.
+pub fn resolve_proxy_policy(
+    app: &Arc<AppState>,
+    provider_name: &str,
+    provider: &Provider,
+    proxy_flag: Option<&str>,
+) -> Result<ProxyPolicy> {
+    if let Some(flag) = proxy_flag {
+        return ProxyPolicy::from_flag(flag);
+    }
+    Ok(app
+        .env
+        .proxy_policies
+        .get(provider_name)
+        .copied()
+        .unwrap_or_else(|| provider.proxy_policy()))
+}
+
+/// Body of a forwarded request.
+pub enum ForwardBody {
+    /// Buffered JSON body, resent on retries
+    Json(Bytes),
+    /// Streamed body (e.g. multipart uploads), sent only once without retries
+    Stream(Body),
+}
+
+/// An OpenAI compatible endpoint, forwarded as is.
+pub struct Endpoint {
+    pub name: &'static str,
+    /// `None` if the provider does not support the endpoint
+    pub url: fn(&Provider) -> Option<Url>,
+    /// Quota charged to the auth key for a request, given its JSON body
+    pub cost: fn(&Provider, &Value) -> i32,
+}
+
+/// Forwards a POST request as is to an endpoint of the provider,
+/// rotating auth keys and proxies like chat completions.
+pub async fn forward_post(
+    app: &Arc<AppState>,
+    provider_name: &str,
+    proxy_flag: Option<&str>,
+    caller: &Caller,
+    endpoint: &Endpoint,
+    mut headers: HeaderMap,
+    body: ForwardBody,
+) -> Response<Body> {
+    let provider = match app.get_provider(provider_name).await {
+        Some(provider) => provider,
+        None => {
+            let msg = format!("Provider not found: {}", provider_name);
+            tracing::warn!(msg);
+            return (StatusCode::NOT_FOUND, msg).into_response();
+        }
+    };
+
+    let Some(url) = (endpoint.url)(&provider) else {
+        let msg = format!(
+            "Provider {} does not support {}",
+            provider_name, endpoint.name
+        );
+        tracing::warn!(msg);
+        return (StatusCode::NOT_FOUND, msg).into_response();
+    };
+
+    let policy = match resolve_proxy_policy(app, provider_name, &provider, proxy_flag) {
+        Ok(policy) => policy,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+
+    let (json, stream) = match body {
+        ForwardBody::Json(body) => (Some(body), None),
+        ForwardBody::Stream(body) => (None, Some(body)),
+    };
+    // a streamed body can not be replayed
+    let policy = match stream {
+        Some(_) => policy.single_attempt(),
+        None => policy,
+    };
+
+    let json_body = json
+        .as_ref()
+        .and_then(|body| serde_json::from_slice::<Value>(body).ok())
+        .unwrap_or_default();
+    tracing::info!(
+        "[POST] {} {} {} - {}",
+        policy,
+        provider_name,
+        endpoint.name,
+        json_body["model"].as_str().unwrap_or_default()
+    );
+
+    let access = ModelAccess::new(app, provider_name, caller).await;
+    match json_body["model"].as_str() {
+        Some(model) if !access.allows(model) => return model_forbidden(model),
+        // the model of streamed multipart bodies is not known without buffering them,
+        // so only callers allowed every model may send them
+        None if stream.is_some() && !access.is_unrestricted() => return unknown_model_forbidden(),
+        _ => {}
+    }
+
+    // streamed bodies keep their own content type, e.g. the multipart boundary
+    let content_headers = [header::CONTENT_TYPE, header::CONTENT_LENGTH]
+        .into_iter()
+        .filter_map(|name| Some((name.clone(), headers.get(&name)?.clone())))
+        .collect::<Vec<_>>();
+    provider.post_header_modifier(&mut headers);
+    if stream.is_some() {
+        // `insert` replaces the json content type set by the provider, `extend` would append
+        for (name, value) in content_headers {
+            headers.insert(name, value);
+        }
+    }
+    let auth = provider.apply_auth(app, provider_name, &mut headers).await;
+
+    let stream = Mutex::new(stream.map(|body| reqwest::Body::wrap_stream(body.into_data_stream())));
+    let picked = PickedProxy::default();
+    let upstream = send_upstream(app, policy, provider.timeouts(), &auth, &picked, |client| {
+        let body = match &json {
+            Some(body) => reqwest::Body::from(body.clone()),
+            None => stream
+                .lock()
+                .unwrap()
+                .take()
+                .expect("streamed body is sent only once"),
+        };
+        client.post(url.clone()).body(body).headers(headers.clone())
+    })
+    .await;
+    let (res, proxy) = match upstream {
+        Ok(upstream) => (upstream.res, upstream.proxy),
+        Err(res) => {
+            refund_lease(app, &auth);
+            return res;
+        }
+    };
+
+    let status = res.status();
+    let cost = (endpoint.cost)(&provider, &json_body);
+    update_auth_state_on_response(app, &auth, &status, cost);
+    // only disable the proxy if no auth key was sent, neither picked nor the client's own
+    if status == StatusCode::TOO_MANY_REQUESTS
+        && auth.is_none()
+        && headers.get(header::AUTHORIZATION).is_none()
+    {
+        disable_failed_proxy(app, &proxy).await;
+    }
+
+    get_response_stream(res).await
+}
+
+/// The proxy last picked by one of the requests sent for the same call,
+/// avoided by the others so that they egress from another proxy.
+pub type PickedProxy = Mutex<Option<Arc<Proxy>>>;
+
+/// Sends a request to the provider, directly or through a proxy according to the policy.
+/// `build` is called again for every retry.
+pub async fn send_upstream<F>(
+    app: &Arc<AppState>,
+    policy: ProxyPolicy,
+    timeouts: Timeouts,
+    auth: &Option<Arc<Mutex<ProviderAuth>>>,
+    picked: &PickedProxy,
+    build: F,
+) -> Result<Upstream, Response<Body>>
+where
+    F: Fn(&Client) -> RequestBuilder,
+{
+    match policy {
+        ProxyPolicy::Never => send_via(app, false, timeouts, auth, picked, &build).await,
+        ProxyPolicy::Always => send_via(app, true, timeouts, auth, picked, &build).await,
+        ProxyPolicy::FallbackToProxyOn429 => {
+            let upstream = send_via(app, false, timeouts, auth, picked, &build).await?;
+            if upstream.res.status() != StatusCode::TOO_MANY_REQUESTS {
+                return Ok(upstream);
+            }
+            tracing::warn!("[Proxy] Rate limited without proxy, retrying through proxy");
+            send_via(app, true, timeouts, auth, picked, &build).await
+        }
+        ProxyPolicy::FallbackToDirectOnProxyError => {
+            match send_via(app, true, timeouts, auth, picked, &build).await {
+                Ok(upstream) => Ok(upstream),
+                Err(_) => {
+                    tracing::warn!("[Proxy] Proxied request failed, retrying without proxy");
+                    send_via(app, false, timeouts, auth, picked, &build).await
+                }
+            }
+        }
+    }
+}
+
+async fn send_via<F>(
+    app: &Arc<AppState>,
+    via_proxy: bool,
+    timeouts: Timeouts,
+    auth: &Option<Arc<Mutex<ProviderAuth>>>,
+    picked: &PickedProxy,
+    build: &F,
+) -> Result<Upstream, Response<Body>>
+where
+    F: Fn(&Client) -> RequestBuilder,
+{
+    let client = match via_proxy {
+        true => {
+            let except = picked.lock().unwrap().clone();
+            let client =
+                create_proxied_client(app, auth_id(auth), except.as_deref(), timeouts.connect)
+                    .await;
+            if let Ok((_, proxy)) = &client {
+                *picked.lock().unwrap() = proxy.clone();
+            }
+            client
+        }
+        false => Client::builder()
+            .connect_timeout(timeouts.connect)
+            .build()
+            .map(|client| (client, None))
+            .map_err(eyre::Report::from),
+    };
+    let (client, proxy) = match client {
+        Ok(result) => result,
+        Err(e) => {
+            let msg = format!("Failed to create reqwest client: {}", e);
+            tracing::error!("{}", msg);
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, msg).into_response());
+        }
+    };
+
+    match tokio::time::timeout(timeouts.first_byte, build(&client).send()).await {
+        Ok(Ok(res)) => {
+            let res = watch_body(app, res, timeouts.idle, &proxy, auth);
+            Ok(Upstream { res, proxy })
+        }
+        Ok(Err(err)) => {
+            record_upstream_failure(app, Failure::Connection, &proxy, auth).await;
+            let msg = "Error sending request";
+            tracing::error!("{}: {} - {:?}", msg, err, proxy);
+            Err((StatusCode::INTERNAL_SERVER_ERROR, msg).into_response())
+        }
+        Err(_) => {
+            record_upstream_failure(app, Failure::Upstream, &proxy, auth).await;
+            let msg = format!("No response within {:?}", timeouts.first_byte);
+            tracing::error!("[Timeout] {} - {:?}", msg, proxy);
+            Err((StatusCode::GATEWAY_TIMEOUT, msg).into_response())
+        }
+    }
+}
+
+/// How a request failed without a complete response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Failure {
+    /// The connection failed or was dropped
+    Connection,
+    /// The upstream was too slow or ended its response early
+    Upstream,
+}
+
+/// Records a request that failed without a complete response: connection failures through
+/// a proxy are blamed on the proxy, which is disabled, the others on the auth key.
+pub async fn record_upstream_failure(
+    app: &Arc<AppState>,
+    failure: Failure,
+    proxy: &Option<Arc<Proxy>>,
+    auth: &Option<Arc<Mutex<ProviderAuth>>>,
+) {
+    match (failure, proxy) {
+        (Failure::Connection, Some(_)) => disable_failed_proxy(app, proxy).await,
+        _ => record_auth_failure(app, auth),
+    }
+}
+
+/// Ends the body of a response with an error if no chunk arrives within `idle`.
+/// Idle and dropped bodies are recorded like requests without a response.
+fn watch_body(
+    app: &Arc<AppState>,
+    res: reqwest::Response,
+    idle: Duration,
+    proxy: &Option<Arc<Proxy>>,
+    auth: &Option<Arc<Mutex<ProviderAuth>>>,
+) -> reqwest::Response {
+    let status = res.status();
+    let version = res.version();
+    let headers = res.headers().clone();
+
+    let state = (
+        Box::pin(res.bytes_stream()),
+        (app.clone(), proxy.clone(), auth.clone()),
+        false,
+    );
+    let stream = futures::stream::unfold(state, move |(mut stream, health, ended)| async move {
+        if ended {
+            return None;
+        }
+        match tokio::time::timeout(idle, stream.next()).await {
+            Ok(Some(Ok(chunk))) => Some((Ok(chunk), (stream, health, false))),
+            Ok(Some(Err(e))) => {
+                let (app, proxy, auth) = &health;
+                tracing::error!("[Stream] Body dropped: {} - {:?}", e, proxy);
+                record_upstream_failure(app, Failure::Connection, proxy, auth).await;
+                Some((Err(BoxError::from(e)), (stream, health, true)))
+            }
+            Ok(None) => None,
+            Err(_) => {
+                let (app, proxy, auth) = &health;
+                tracing::error!("[Timeout] Stream idle for {:?} - {:?}", idle, proxy);
+                record_upstream_failure(app, Failure::Upstream, proxy, auth).await;
+                let msg = format!("Stream idle for {:?}", idle);
+                let error = std::io::Error::new(std::io::ErrorKind::TimedOut, msg);
+                Some((Err(BoxError::from(error)), (stream, health, true)))
+            }
+        }
+    });
+
+    let mut res = axum::http::Response::new(reqwest::Body::wrap_stream(stream));
+    *res.status_mut() = status;
+    *res.version_mut() = version;
+    *res.headers_mut() = headers;
+    reqwest::Response::from(res)
+}
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// A response received from the provider, along with the auth key it was sent with.
+pub struct Attempt {
+    pub upstream: Upstream,
+    pub auth: Option<Arc<Mutex<ProviderAuth>>>,
+}
+
+/// Sends a request with an auth key picked from the provider.
+///
+/// With a `HEDGE_DELAY` for the provider, a second request is sent with another key
+/// if the first has not responded within the delay, through another proxy than the
+/// first unless it is the only one. The first to respond wins, the other is aborted
+/// and not charged, its lease given back with `AUTH_LEASING`.
+pub async fn send_hedged<F>(
+    app: &Arc<AppState>,
+    provider_name: &str,
+    provider: &Provider,
+    policy: ProxyPolicy,
+    headers: &HeaderMap,
+    build: F,
+) -> Result<Attempt, Response<Body>>
+where
+    F: Fn(&Client, &HeaderMap) -> RequestBuilder,
+{
+    let picked = PickedProxy::default();
+    let attempt = |auth: Option<Arc<Mutex<ProviderAuth>>>| {
+        let mut headers = headers.clone();
+        provider.apply_picked_auth(&mut headers, &auth);
+        let build = &build;
+        let picked = &picked;
+        Box::pin(async move {
+            let timeouts = provider.timeouts();
+            let upstream = send_upstream(app, policy, timeouts, &auth, picked, |client| {
+                build(client, &headers)
+            })
+            .await;
+            match upstream {
+                Ok(upstream) => Ok(Attempt { upstream, auth }),
+                Err(res) => {
+                    // sent without a response, not charged
+                    refund_lease(app, &auth);
+                    Err(res)
+                }
+            }
+        })
+    };
+
+    let first_auth = provider.lease_auth(app, provider_name, None).await;
+    let first = attempt(first_auth.clone());
+    let Some(delay) = app.env.hedge_delays.get(provider_name).copied() else {
+        return first.await;
+    };
+    let first = match select(first, Box::pin(tokio::time::sleep(delay))).await {
+        Either::Left((result, _)) => return result,
+        Either::Right((_, first)) => first,
+    };
+
+    let second_auth = match &first_auth {
+        Some(_) => match provider
+            .lease_auth(app, provider_name, auth_id(&first_auth))
+            .await
+        {
+            Some(auth) => Some(auth),
+            None => return first.await,
+        },
+        None => None,
+    };
+    tracing::warn!(
+        "[Hedge] {} did not respond within {:?}, sending a second request",
+        provider_name,
+        delay
+    );
+    let second = attempt(second_auth.clone());
+
+    let (result, other, other_auth) = match select(first, second).await {
+        Either::Left((result, other)) => (result, other, second_auth),
+        Either::Right((result, other)) => (result, other, first_auth),
+    };
+    match result {
+        Ok(attempt) => {
+            match other.now_or_never() {
+                // the other request also completed, the upstream served it
+                Some(Ok(other)) => {
+                    update_auth_state_on_response(app, &other.auth, &other.upstream.res.status(), 1)
+                }
+                // aborted before a response, a failed one is already given back
+                None => refund_lease(app, &other_auth),
+                Some(Err(_)) => {}
+            }
+            Ok(attempt)
+        }
+        Err(_) => other.await,
+    }
+}
+
+/// Id of the picked auth key, used to bind the key to its proxy.
+fn auth_id(auth: &Option<Arc<Mutex<ProviderAuth>>>) -> Option<i32> {
+    auth.as_ref().map(|auth| auth.lock().unwrap().id)
+}
@@ -0,0 +1,50 @@
+use crate::{app_state::AppState, db::StorageFn as _, providers::auth::sync_auth};
+use std::{sync::Arc, time::Duration};
+
+/// Time given to in-flight requests and streams to finish once shutting down,
+/// within fly's `kill_timeout`
+pub const SHUTDOWN_DEADLINE: Duration = Duration::from_secs(20);
+
+/// Resolves on SIGTERM, or SIGINT (ctrl-c), which fly sends by default.
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!("[Shutdown] Failed to listen for ctrl-c: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                tracing::error!("[Shutdown] Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => tracing::info!("[Shutdown] Received SIGINT"),
+        _ = terminate => tracing::info!("[Shutdown] Received SIGTERM"),
+    }
+}
+
+/// Writes the in-memory state that would be lost on exit to the database:
+/// auth counters and cooldowns, and the proxy pool without the disabled proxies.
+pub async fn flush_state(app: &Arc<AppState>) {
+    if let Err(e) = sync_auth(app).await {
+        tracing::error!("[Shutdown] Failed to sync auth: {}", e);
+    }
+
+    let proxies = app.proxies.lock().await.clone();
+    match app.storage.save_proxies(&proxies).await {
+        Ok(()) => tracing::info!("[Shutdown] Saved {} proxies", proxies.len()),
+        Err(e) => tracing::error!("[Shutdown] Failed to save proxies: {}", e),
+    }
+}
@@ -0,0 +1,608 @@
+use crate::utils::sse::{format_event, is_event_stream, SseParser};
+use axum::{
+    body::{Body, Bytes},
+    http::{header, Response, StatusCode},
+    response::IntoResponse,
+};
+use futures::StreamExt as _;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+#[derive(Deserialize, Debug)]
+pub struct MessagesRequest {
+    pub model: String,
+    pub messages: Vec<Message>,
+    pub system: Option<Content>,
+    pub max_tokens: Option<u32>,
+    pub stop_sequences: Option<Vec<String>>,
+    pub stream: Option<bool>,
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub tools: Option<Vec<Tool>>,
+    pub tool_choice: Option<ToolChoice>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Message {
+    pub role: String,
+    pub content: Content,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub enum Content {
+    Text(String),
+    Blocks(Vec<ContentBlock>),
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentBlock {
+    Text {
+        text: String,
+    },
+    Image {
+        source: ImageSource,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        input: Value,
+    },
+    ToolResult {
+        tool_use_id: String,
+        content: Option<Content>,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ImageSource {
+    Base64 { media_type: String, data: String },
+    Url { url: String },
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Tool {
+    pub name: String,
+    pub description: Option<String>,
+    pub input_schema: Value,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ToolChoice {
+    Auto,
+    Any,
+    Tool { name: String },
+    None,
+}
+
+impl Content {
+    /// Concatenated text of all text blocks.
+    pub fn text(&self) -> String {
+        match self {
+            Content::Text(text) => text.clone(),
+            Content::Blocks(blocks) => blocks
+                .iter()
+                .filter_map(|block| match block {
+                    ContentBlock::Text { text } => Some(text.as_str()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+        }
+    }
+}
+
+impl ImageSource {
+    fn to_url(&self) -> String {
+        match self {
+            ImageSource::Base64 { media_type, data } => {
+                format!("data:{};base64,{}", media_type, data)
+            }
+            ImageSource::Url { url } => url.clone(),
+        }
+    }
+}
+
+impl Message {
+    /// Appends the OpenAI messages equivalent to this message.
+    /// Tool results become separate `tool` messages.
+    fn append_chat_messages(&self, messages: &mut Vec<Value>) {
+        let blocks = match &self.content {
+            Content::Text(text) => {
+                messages.push(json!({ "role": self.role, "content": text }));
+                return;
+            }
+            Content::Blocks(blocks) => blocks,
+        };
+
+        let mut parts = vec![];
+        let mut tool_calls = vec![];
+        for block in blocks {
+            match block {
+                ContentBlock::Text { text } => parts.push(json!({ "type": "text", "text": text })),
+                ContentBlock::Image { source } => parts.push(json!({
+                    "type": "image_url",
+                    "image_url": { "url": source.to_url() },
+                })),
+                ContentBlock::ToolUse { id, name, input } => tool_calls.push(json!({
+                    "id": id,
+                    "type": "function",
+                    "function": { "name": name, "arguments": input.to_string() },
+                })),
+                ContentBlock::ToolResult {
+                    tool_use_id,
+                    content,
+                } => messages.push(json!({
+                    "role": "tool",
+                    "tool_call_id": tool_use_id,
+                    "content": content.as_ref().map(Content::text).unwrap_or_default(),
+                })),
+                ContentBlock::Other => (),
+            }
+        }
+
+        // plain text is the most widely supported content shape
+        let content = if parts.iter().all(|part| part["type"] == "text") {
+            let text = parts
+                .iter()
+                .filter_map(|part| part["text"].as_str())
+                .collect::<Vec<_>>()
+                .join("\n");
+            match text.is_empty() && !tool_calls.is_empty() {
+                true => Value::Null,
+                false => Value::String(text),
+            }
+        } else {
+            Value::Array(parts)
+        };
+
+        if content.as_str().is_some_and(str::is_empty) && tool_calls.is_empty() {
+            // only tool results in this message
+            return;
+        }
+
+        let mut message = json!({ "role": self.role, "content": content });
+        if !tool_calls.is_empty() {
+            message["tool_calls"] = Value::Array(tool_calls);
+        }
+        messages.push(message);
+    }
+}
+
+impl MessagesRequest {
+    /// Translates the request into an OpenAI chat completions request.
+    pub fn to_chat_body(&self) -> Value {
+        let mut messages = vec![];
+        if let Some(system) = &self.system {
+            messages.push(json!({ "role": "system", "content": system.text() }));
+        }
+        for message in &self.messages {
+            message.append_chat_messages(&mut messages);
+        }
+
+        let mut body = json!({ "model": self.model, "messages": messages });
+        if let Some(max_tokens) = self.max_tokens {
+            body["max_tokens"] = json!(max_tokens);
+        }
+        if let Some(stop_sequences) = &self.stop_sequences {
+            body["stop"] = json!(stop_sequences);
+        }
+        if let Some(temperature) = self.temperature {
+            body["temperature"] = json!(temperature);
+        }
+        if let Some(top_p) = self.top_p {
+            body["top_p"] = json!(top_p);
+        }
+        if self.is_stream() {
+            body["stream"] = json!(true);
+            body["stream_options"] = json!({ "include_usage": true });
+        }
+        if let Some(tools) = &self.tools {
+            body["tools"] = tools
+                .iter()
+                .map(|tool| {
+                    json!({
+                        "type": "function",
+                        "function": {
+                            "name": tool.name,
+                            "description": tool.description,
+                            "parameters": tool.input_schema,
+                        },
+                    })
+                })
+                .collect();
+        }
+        if let Some(tool_choice) = &self.tool_choice {
+            body["tool_choice"] = match tool_choice {
+                ToolChoice::Auto => json!("auto"),
+                ToolChoice::Any => json!("required"),
+                ToolChoice::None => json!("none"),
+                ToolChoice::Tool { name } => {
+                    json!({ "type": "function", "function": { "name": name } })
+                }
+            };
+        }
+        body
+    }
+
+    pub fn is_stream(&self) -> bool {
+        self.stream.unwrap_or(false)
+    }
+}
+
+fn stop_reason(finish_reason: &str) -> &'static str {
+    match finish_reason {
+        "length" => "max_tokens",
+        "tool_calls" | "function_call" => "tool_use",
+        "content_filter" => "refusal",
+        _ => "end_turn",
+    }
+}
+
+fn error_type(status: StatusCode) -> &'static str {
+    match status {
+        StatusCode::BAD_REQUEST => "invalid_request_error",
+        StatusCode::UNAUTHORIZED => "authentication_error",
+        StatusCode::FORBIDDEN => "permission_error",
+        StatusCode::NOT_FOUND => "not_found_error",
+        StatusCode::PAYLOAD_TOO_LARGE => "request_too_large",
+        StatusCode::TOO_MANY_REQUESTS => "rate_limit_error",
+        _ => "api_error",
+    }
+}
+
+/// Creates an Anthropic error response.
+pub fn error_response(status: StatusCode, message: &str) -> Response<Body> {
+    let body = json!({
+        "type": "error",
+        "error": { "type": error_type(status), "message": message },
+    });
+    (
+        status,
+        [(header::CONTENT_TYPE, "application/json")],
+        body.to_string(),
+    )
+        .into_response()
+}
+
+fn new_message_id() -> String {
+    format!("msg_{}", chrono::Utc::now().timestamp_micros())
+}
+
+/// Translates an OpenAI chat completion into an Anthropic message.
+pub fn to_messages_response(chat: &Value, model: &str) -> Value {
+    let choice = &chat["choices"][0];
+    let message = &choice["message"];
+
+    let mut content = vec![];
+    if let Some(text) = message["content"].as_str().filter(|text| !text.is_empty()) {
+        content.push(json!({ "type": "text", "text": text }));
+    }
+    for call in message["tool_calls"].as_array().into_iter().flatten() {
+        let arguments = call["function"]["arguments"].as_str().unwrap_or("{}");
+        content.push(json!({
+            "type": "tool_use",
+            "id": call["id"],
+            "name": call["function"]["name"],
+            "input": serde_json::from_str::<Value>(arguments).unwrap_or(json!({})),
+        }));
+    }
+
+    json!({
+        "id": chat["id"].as_str().map(str::to_owned).unwrap_or_else(new_message_id),
+        "type": "message",
+        "role": "assistant",
+        "model": chat["model"].as_str().unwrap_or(model),
+        "content": content,
+        "stop_reason": stop_reason(choice["finish_reason"].as_str().unwrap_or("stop")),
+        "stop_sequence": null,
+        "usage": {
+            "input_tokens": chat["usage"]["prompt_tokens"].as_u64().unwrap_or(0),
+            "output_tokens": chat["usage"]["completion_tokens"].as_u64().unwrap_or(0),
+        },
+    })
+}
+
+#[derive(Debug, PartialEq)]
+enum Block {
+    Text,
+    /// Index of the tool call in the OpenAI stream
+    ToolUse(u64),
+}
+
+/// Translates an OpenAI chat completion stream into Anthropic stream events.
+#[derive(Debug)]
+pub struct MessagesStream {
+    model: String,
+    parser: SseParser,
+    started: bool,
+    finished: bool,
+    block: Option<Block>,
+    index: usize,
+    stop_reason: Option<&'static str>,
+    input_tokens: u64,
+    output_tokens: u64,
+}
+
+impl MessagesStream {
+    pub fn new(model: String) -> Self {
+        Self {
+            model,
+            parser: SseParser::new(),
+            started: false,
+            finished: false,
+            block: None,
+            index: 0,
+            stop_reason: None,
+            input_tokens: 0,
+            output_tokens: 0,
+        }
+    }
+
+    /// Feeds a chunk of the OpenAI stream, returning the translated events.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<Bytes> {
+        let mut events = vec![];
+        for event in self.parser.push(chunk) {
+            if event.is_done() {
+                events.append(&mut self.finish());
+                continue;
+            }
+            match serde_json::from_str::<Value>(&event.data) {
+                Ok(chunk) => self.handle_chunk(&chunk, &mut events),
+                Err(e) => tracing::warn!("Error parsing stream chunk: {}", e),
+            }
+        }
+        events
+    }
+
+    /// Closes the message, if not closed yet.
+    pub fn finish(&mut self) -> Vec<Bytes> {
+        let mut events = vec![];
+        if self.finished {
+            return events;
+        }
+        self.finished = true;
+        self.start(&Value::Null, &mut events);
+        self.close_block(&mut events);
+        events.push(format_event(
+            Some("message_delta"),
+            &json!({
+                "type": "message_delta",
+                "delta": {
+                    "stop_reason": self.stop_reason.unwrap_or("end_turn"),
+                    "stop_sequence": null,
+                },
+                "usage": { "output_tokens": self.output_tokens },
+            })
+            .to_string(),
+        ));
+        events.push(format_event(
+            Some("message_stop"),
+            &json!({ "type": "message_stop" }).to_string(),
+        ));
+        events
+    }
+
+    fn start(&mut self, chunk: &Value, events: &mut Vec<Bytes>) {
+        if self.started {
+            return;
+        }
+        self.started = true;
+        let id = chunk["id"]
+            .as_str()
+            .map(str::to_owned)
+            .unwrap_or_else(new_message_id);
+        events.push(format_event(
+            Some("message_start"),
+            &json!({
+                "type": "message_start",
+                "message": {
+                    "id": id,
+                    "type": "message",
+                    "role": "assistant",
+                    "model": self.model,
+                    "content": [],
+                    "stop_reason": null,
+                    "stop_sequence": null,
+                    "usage": { "input_tokens": self.input_tokens, "output_tokens": 0 },
+                },
+            })
+            .to_string(),
+        ));
+    }
+
+    fn open_block(&mut self, block: Block, content_block: Value, events: &mut Vec<Bytes>) {
+        self.close_block(events);
+        events.push(format_event(
+            Some("content_block_start"),
+            &json!({
+                "type": "content_block_start",
+                "index": self.index,
+                "content_block": content_block,
+            })
+            .to_string(),
+        ));
+        self.block = Some(block);
+    }
+
+    fn close_block(&mut self, events: &mut Vec<Bytes>) {
+        if self.block.take().is_some() {
+            events.push(format_event(
+                Some("content_block_stop"),
+                &json!({ "type": "content_block_stop", "index": self.index }).to_string(),
+            ));
+            self.index += 1;
+        }
+    }
+
+    fn delta(&self, delta: Value, events: &mut Vec<Bytes>) {
+        events.push(format_event(
+            Some("content_block_delta"),
+            &json!({ "type": "content_block_delta", "index": self.index, "delta": delta })
+                .to_string(),
+        ));
+    }
+
+    /// Ends the message with an `error` event instead of `message_stop`.
+    fn fail(&mut self, error: &Value, events: &mut Vec<Bytes>) {
+        self.finished = true;
+        let message = error["message"]
+            .as_str()
+            .unwrap_or("Upstream stream failed");
+        events.push(format_event(
+            Some("error"),
+            &json!({
+                "type": "error",
+                "error": { "type": "api_error", "message": message },
+            })
+            .to_string(),
+        ));
+    }
+
+    fn handle_chunk(&mut self, chunk: &Value, events: &mut Vec<Bytes>) {
+        if self.finished {
+            return;
+        }
+        if chunk["error"].is_object() {
+            return self.fail(&chunk["error"], events);
+        }
+        if let Some(usage) = chunk["usage"].as_object() {
+            let tokens = |key: &str| usage.get(key).and_then(Value::as_u64).unwrap_or(0);
+            self.input_tokens = tokens("prompt_tokens");
+            self.output_tokens = tokens("completion_tokens");
+        }
+        self.start(chunk, events);
+
+        let Some(choice) = chunk["choices"].get(0) else {
+            return;
+        };
+        let delta = &choice["delta"];
+
+        if let Some(text) = delta["content"].as_str().filter(|text| !text.is_empty()) {
+            if self.block != Some(Block::Text) {
+                self.open_block(Block::Text, json!({ "type": "text", "text": "" }), events);
+            }
+            self.delta(json!({ "type": "text_delta", "text": text }), events);
+        }
+
+        for call in delta["tool_calls"].as_array().into_iter().flatten() {
+            let call_index = call["index"].as_u64().unwrap_or(0);
+            if self.block != Some(Block::ToolUse(call_index)) {
+                let id = call["id"]
+                    .as_str()
+                    .map(str::to_owned)
+                    .unwrap_or_else(|| format!("toolu_{}", call_index));
+                self.open_block(
+                    Block::ToolUse(call_index),
+                    json!({
+                        "type": "tool_use",
+                        "id": id,
+                        "name": call["function"]["name"],
+                        "input": {},
+                    }),
+                    events,
+                );
+            }
+            if let Some(arguments) = call["function"]["arguments"]
+                .as_str()
+                .filter(|arguments| !arguments.is_empty())
+            {
+                self.delta(
+                    json!({ "type": "input_json_delta", "partial_json": arguments }),
+                    events,
+                );
+            }
+        }
+
+        if let Some(finish_reason) = choice["finish_reason"].as_str() {
+            self.stop_reason = Some(stop_reason(finish_reason));
+        }
+    }
+}
+
+/// Translates an OpenAI chat completion response into an Anthropic response.
+pub async fn translate_response(res: Response<Body>, model: &str) -> Response<Body> {
+    let status = res.status();
+    let is_stream = is_event_stream(res.headers());
+
+    if status.is_success() && is_stream {
+        let mut state = MessagesStream::new(model.to_owned());
+        let stream = res.into_body().into_data_stream();
+        let stream = stream
+            .map(Some)
+            .chain(futures::stream::once(async { None }))
+            .map(move |chunk| match chunk {
+                Some(Ok(chunk)) => Ok(Bytes::from(state.push(&chunk).concat())),
+                Some(Err(e)) => Err(e),
+                None => Ok(Bytes::from(state.finish().concat())),
+            });
+        return (
+            status,
+            [
+                (header::CONTENT_TYPE, "text/event-stream"),
+                (header::CACHE_CONTROL, "no-cache"),
+            ],
+            Body::from_stream(stream),
+        )
+            .into_response();
+    }
+
+    let body = match axum::body::to_bytes(res.into_body(), usize::MAX).await {
+        Ok(body) => body,
+        Err(e) => return error_response(StatusCode::BAD_GATEWAY, &e.to_string()),
+    };
+
+    if !status.is_success() {
+        let message = serde_json::from_slice::<Value>(&body)
+            .ok()
+            .and_then(|body| body["error"]["message"].as_str().map(str::to_owned))
+            .unwrap_or_else(|| String::from_utf8_lossy(&body).into_owned());
+        return error_response(status, &message);
+    }
+
+    match serde_json::from_slice::<Value>(&body) {
+        Ok(chat) => (
+            status,
+            [(header::CONTENT_TYPE, "application/json")],
+            to_messages_response(&chat, model).to_string(),
+        )
+            .into_response(),
+        Err(e) => {
+            tracing::warn!("Error parsing chat response: {}", e);
+            error_response(StatusCode::BAD_GATEWAY, &e.to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(events: &[Bytes]) -> String {
+        events
+            .iter()
+            .map(|event| String::from_utf8_lossy(event))
+            .collect()
+    }
+
+    #[test]
+    fn ends_a_failed_stream_with_an_error_event() {
+        let part = r#"data: {"id":"a","choices":[{"index":0,"delta":{"content":"a"}}]}"#;
+        let error = r#"data: {"error":{"message":"Upstream stream ended unexpectedly","type":"upstream_error","code":"stream_interrupted"}}"#;
+        let mut stream = MessagesStream::new("claude".to_owned());
+        let mut events = stream.push(format!("{}\n\n{}\n\n", part, error).as_bytes());
+        events.append(&mut stream.finish());
+
+        let last = data(&events[events.len() - 1..]);
+        assert!(last.starts_with("event: error\n"));
+        assert!(last.contains(r#""message":"Upstream stream ended unexpectedly""#));
+        let events = data(&events);
+        assert!(events.contains("event: content_block_delta"));
+        assert!(!events.contains("message_stop"));
+    }
+}
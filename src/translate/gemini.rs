@@ -0,0 +1,511 @@
+use crate::utils::{
+    sse::{format_event, SseParser},
+    stream_body::{get_body_stream, get_response_stream},
+};
+use axum::{
+    body::{Body, Bytes},
+    http::{header, Response, StatusCode},
+    response::IntoResponse,
+};
+use futures::StreamExt as _;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+
+/// Thinking budgets used for OpenAI's `reasoning_effort`
+fn thinking_budget(reasoning_effort: &str) -> Option<i64> {
+    match reasoning_effort {
+        "none" => Some(0),
+        "low" => Some(1024),
+        "medium" => Some(8192),
+        "high" => Some(24576),
+        _ => None,
+    }
+}
+
+/// Model id of a chat request, without the `models/` prefix.
+pub fn model_id(chat: &Value) -> String {
+    let model = chat["model"].as_str().unwrap_or_default();
+    model.strip_prefix("models/").unwrap_or(model).to_owned()
+}
+
+pub fn is_stream(chat: &Value) -> bool {
+    chat["stream"].as_bool().unwrap_or(false)
+}
+
+fn text_of(content: &Value) -> String {
+    match content {
+        Value::String(text) => text.clone(),
+        Value::Array(parts) => parts
+            .iter()
+            .filter_map(|part| part["text"].as_str())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    }
+}
+
+fn image_part(url: &str) -> Value {
+    if let Some((mime_type, data)) = url
+        .strip_prefix("data:")
+        .and_then(|url| url.split_once(";base64,"))
+    {
+        return json!({ "inlineData": { "mimeType": mime_type, "data": data } });
+    }
+    let extension = url.rsplit('.').next().unwrap_or_default().to_lowercase();
+    let mime_type = match extension.as_str() {
+        "png" => "image/png",
+        "webp" => "image/webp",
+        "gif" => "image/gif",
+        _ => "image/jpeg",
+    };
+    json!({ "fileData": { "fileUri": url, "mimeType": mime_type } })
+}
+
+fn content_parts(content: &Value) -> Vec<Value> {
+    match content {
+        Value::String(text) => vec![json!({ "text": text })],
+        Value::Array(parts) => parts
+            .iter()
+            .filter_map(|part| match part["type"].as_str() {
+                Some("text") => Some(json!({ "text": part["text"] })),
+                Some("image_url") => part["image_url"]["url"].as_str().map(image_part),
+                _ => None,
+            })
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// Translates an OpenAI chat completions request into a Gemini `generateContent` request.
+pub fn to_generate_content(chat: &Value) -> Value {
+    let mut system_parts = vec![];
+    let mut contents: Vec<Value> = vec![];
+    // function names by tool call id, tool results only reference the id
+    let mut tool_names = HashMap::new();
+
+    for message in chat["messages"].as_array().into_iter().flatten() {
+        let (role, parts) = match message["role"].as_str().unwrap_or_default() {
+            "system" | "developer" => {
+                system_parts.push(json!({ "text": text_of(&message["content"]) }));
+                continue;
+            }
+            "assistant" => {
+                let mut parts = content_parts(&message["content"]);
+                for call in message["tool_calls"].as_array().into_iter().flatten() {
+                    let name = call["function"]["name"].clone();
+                    if let Some(id) = call["id"].as_str() {
+                        tool_names.insert(id.to_owned(), name.clone());
+                    }
+                    let arguments = call["function"]["arguments"].as_str().unwrap_or("{}");
+                    let args = serde_json::from_str::<Value>(arguments).unwrap_or(json!({}));
+                    parts.push(json!({ "functionCall": { "name": name, "args": args } }));
+                }
+                ("model", parts)
+            }
+            "tool" => {
+                let id = message["tool_call_id"].as_str().unwrap_or_default();
+                let name = tool_names.get(id).cloned().unwrap_or(json!(id));
+                let content = text_of(&message["content"]);
+                let response = match serde_json::from_str::<Value>(&content) {
+                    Ok(Value::Object(object)) => Value::Object(object),
+                    _ => json!({ "content": content }),
+                };
+                let part = json!({ "functionResponse": { "name": name, "response": response } });
+                ("user", vec![part])
+            }
+            _ => ("user", content_parts(&message["content"])),
+        };
+        if parts.is_empty() {
+            continue;
+        }
+        // Gemini expects alternating turns
+        match contents.last_mut() {
+            Some(last) if last["role"] == role => {
+                last["parts"].as_array_mut().unwrap().extend(parts);
+            }
+            _ => contents.push(json!({ "role": role, "parts": parts })),
+        }
+    }
+
+    let mut config = Map::new();
+    let mut set = |key: &str, value: &Value| {
+        if !value.is_null() {
+            config.insert(key.to_owned(), value.clone());
+        }
+    };
+    set("temperature", &chat["temperature"]);
+    set("topP", &chat["top_p"]);
+    set("topK", &chat["top_k"]);
+    set("candidateCount", &chat["n"]);
+    set("presencePenalty", &chat["presence_penalty"]);
+    set("frequencyPenalty", &chat["frequency_penalty"]);
+    set("seed", &chat["seed"]);
+    set("maxOutputTokens", &chat["max_tokens"]);
+    set("maxOutputTokens", &chat["max_completion_tokens"]);
+    match &chat["stop"] {
+        Value::String(stop) => set("stopSequences", &json!([stop])),
+        stop => set("stopSequences", stop),
+    }
+    match chat["response_format"]["type"].as_str() {
+        Some("json_object") => set("responseMimeType", &json!("application/json")),
+        Some("json_schema") => {
+            set("responseMimeType", &json!("application/json"));
+            set(
+                "responseJsonSchema",
+                &chat["response_format"]["json_schema"]["schema"],
+            );
+        }
+        _ => (),
+    }
+    if let Some(budget) = chat["reasoning_effort"].as_str().and_then(thinking_budget) {
+        set("thinkingConfig", &json!({ "thinkingBudget": budget }));
+    }
+
+    let mut body = json!({ "contents": contents });
+    if !system_parts.is_empty() {
+        body["systemInstruction"] = json!({ "parts": system_parts });
+    }
+
+    let mut tools = vec![];
+    let declarations = chat["tools"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|tool| tool["type"] == "function")
+        .map(|tool| {
+            let function = &tool["function"];
+            let mut declaration = json!({ "name": function["name"] });
+            if !function["description"].is_null() {
+                declaration["description"] = function["description"].clone();
+            }
+            if !function["parameters"].is_null() {
+                declaration["parametersJsonSchema"] = function["parameters"].clone();
+            }
+            declaration
+        })
+        .collect::<Vec<_>>();
+    if !declarations.is_empty() {
+        tools.push(json!({ "functionDeclarations": declarations }));
+    }
+
+    let tool_config = match &chat["tool_choice"] {
+        Value::String(choice) => match choice.as_str() {
+            "none" => Some(json!({ "mode": "NONE" })),
+            "required" => Some(json!({ "mode": "ANY" })),
+            _ => None,
+        },
+        Value::Object(choice) => Some(json!({
+            "mode": "ANY",
+            "allowedFunctionNames": [choice.get("function").map(|f| &f["name"])],
+        })),
+        _ => None,
+    };
+    if let Some(tool_config) = tool_config {
+        body["toolConfig"] = json!({ "functionCallingConfig": tool_config });
+    }
+
+    // native options, passed as `google` like the OpenAI compatibility `extra_body`
+    let google = match &chat["extra_body"]["google"] {
+        Value::Null => &chat["google"],
+        google => google,
+    };
+    if let Some(safety_settings) = google.get("safety_settings") {
+        body["safetySettings"] = safety_settings.clone();
+    }
+    if let Some(thinking_config) = google.get("thinking_config") {
+        config.insert("thinkingConfig".to_owned(), thinking_config.clone());
+    }
+    if let Some(cached_content) = google.get("cached_content") {
+        body["cachedContent"] = cached_content.clone();
+    }
+    // e.g. grounding with `[{ "google_search": {} }]`
+    if let Some(google_tools) = google.get("tools").and_then(Value::as_array) {
+        tools.extend(google_tools.iter().cloned());
+    }
+
+    if !tools.is_empty() {
+        body["tools"] = Value::Array(tools);
+    }
+    if !config.is_empty() {
+        body["generationConfig"] = Value::Object(config);
+    }
+    body
+}
+
+fn finish_reason(finish_reason: &str, has_tool_calls: bool) -> &'static str {
+    match finish_reason {
+        "MAX_TOKENS" => "length",
+        "SAFETY" | "RECITATION" | "BLOCKLIST" | "PROHIBITED_CONTENT" | "SPII" | "IMAGE_SAFETY" => {
+            "content_filter"
+        }
+        _ if has_tool_calls => "tool_calls",
+        _ => "stop",
+    }
+}
+
+fn usage(response: &Value) -> Value {
+    let metadata = &response["usageMetadata"];
+    let tokens = |key: &str| metadata[key].as_u64().unwrap_or(0);
+    let completion_tokens = tokens("candidatesTokenCount") + tokens("thoughtsTokenCount");
+    json!({
+        "prompt_tokens": tokens("promptTokenCount"),
+        "completion_tokens": completion_tokens,
+        "total_tokens": tokens("totalTokenCount"),
+    })
+}
+
+/// Splits the parts of a candidate into text, reasoning and tool calls.
+/// `tool_index` numbers the tool calls across stream chunks.
+fn candidate_message(candidate: &Value, tool_index: &mut usize) -> (String, String, Vec<Value>) {
+    let mut text = String::new();
+    let mut reasoning = String::new();
+    let mut tool_calls = vec![];
+    for part in candidate["content"]["parts"]
+        .as_array()
+        .into_iter()
+        .flatten()
+    {
+        if let Some(call) = part.get("functionCall") {
+            tool_calls.push(json!({
+                "index": *tool_index,
+                "id": format!("call_{}", tool_index),
+                "type": "function",
+                "function": {
+                    "name": call["name"],
+                    "arguments": call["args"].to_string(),
+                },
+            }));
+            *tool_index += 1;
+        } else if let Some(part_text) = part["text"].as_str() {
+            match part["thought"].as_bool().unwrap_or(false) {
+                true => reasoning.push_str(part_text),
+                false => text.push_str(part_text),
+            }
+        }
+    }
+    (text, reasoning, tool_calls)
+}
+
+fn response_id(response: &Value) -> String {
+    match response["responseId"].as_str() {
+        Some(id) => format!("chatcmpl-{}", id),
+        None => format!("chatcmpl-{}", chrono::Utc::now().timestamp_micros()),
+    }
+}
+
+/// Translates a Gemini `generateContent` response into an OpenAI chat completion.
+pub fn to_chat_response(response: &Value, model: &str) -> Value {
+    let choices = response["candidates"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .enumerate()
+        .map(|(index, candidate)| {
+            let (text, reasoning, tool_calls) = candidate_message(candidate, &mut 0);
+            let mut message = json!({ "role": "assistant", "content": text });
+            if !reasoning.is_empty() {
+                message["reasoning_content"] = json!(reasoning);
+            }
+            let finish_reason = candidate["finishReason"]
+                .as_str()
+                .map(|reason| finish_reason(reason, !tool_calls.is_empty()));
+            if !tool_calls.is_empty() {
+                message["tool_calls"] = Value::Array(tool_calls);
+            }
+            json!({
+                "index": candidate["index"].as_u64().unwrap_or(index as u64),
+                "message": message,
+                "finish_reason": finish_reason,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    json!({
+        "id": response_id(response),
+        "object": "chat.completion",
+        "created": chrono::Utc::now().timestamp(),
+        "model": response["modelVersion"].as_str().unwrap_or(model),
+        "choices": choices,
+        "usage": usage(response),
+    })
+}
+
+/// Translates a Gemini `streamGenerateContent` SSE stream into OpenAI chat completion chunks.
+#[derive(Debug)]
+pub struct GeminiStream {
+    model: String,
+    id: Option<String>,
+    created: i64,
+    parser: SseParser,
+    sent_role: bool,
+    tool_index: usize,
+    /// A candidate got a finish reason, so the stream may end with `[DONE]`
+    finished: bool,
+}
+
+impl GeminiStream {
+    pub fn new(model: String) -> Self {
+        Self {
+            model,
+            id: None,
+            created: chrono::Utc::now().timestamp(),
+            parser: SseParser::new(),
+            sent_role: false,
+            tool_index: 0,
+            finished: false,
+        }
+    }
+
+    /// Feeds a chunk of the Gemini stream, returning the translated events.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<Bytes> {
+        let mut events = vec![];
+        for event in self.parser.push(chunk) {
+            match serde_json::from_str::<Value>(&event.data) {
+                Ok(response) => events.push(self.translate(&response)),
+                Err(e) => tracing::warn!("Error parsing Gemini stream chunk: {}", e),
+            }
+        }
+        events
+    }
+
+    pub fn finish(&mut self) -> Vec<Bytes> {
+        let mut events = vec![];
+        if let Some(event) = self.parser.finish() {
+            match serde_json::from_str::<Value>(&event.data) {
+                Ok(response) => events.push(self.translate(&response)),
+                Err(e) => tracing::warn!("Error parsing Gemini stream chunk: {}", e),
+            }
+        }
+        // a stream cut off before a finish reason is left without `[DONE]`, so it shows as truncated
+        if self.finished {
+            events.push(format_event(None, "[DONE]"));
+        }
+        events
+    }
+
+    fn translate(&mut self, response: &Value) -> Bytes {
+        let id = self.id.get_or_insert_with(|| response_id(response)).clone();
+        let mut finished = false;
+        let choices = response["candidates"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .enumerate()
+            .map(|(index, candidate)| {
+                let (text, reasoning, tool_calls) =
+                    candidate_message(candidate, &mut self.tool_index);
+                let mut delta = json!({});
+                if !self.sent_role {
+                    delta["role"] = json!("assistant");
+                }
+                if !text.is_empty() {
+                    delta["content"] = json!(text);
+                }
+                if !reasoning.is_empty() {
+                    delta["reasoning_content"] = json!(reasoning);
+                }
+                let finish_reason = candidate["finishReason"].as_str().map(|reason| {
+                    finish_reason(reason, !tool_calls.is_empty() || self.tool_index > 0)
+                });
+                finished |= finish_reason.is_some();
+                if !tool_calls.is_empty() {
+                    delta["tool_calls"] = Value::Array(tool_calls);
+                }
+                json!({
+                    "index": candidate["index"].as_u64().unwrap_or(index as u64),
+                    "delta": delta,
+                    "finish_reason": finish_reason,
+                })
+            })
+            .collect::<Vec<_>>();
+        self.sent_role = true;
+
+        let mut chunk = json!({
+            "id": id,
+            "object": "chat.completion.chunk",
+            "created": self.created,
+            "model": response["modelVersion"].as_str().unwrap_or(&self.model),
+            "choices": choices,
+        });
+        if finished {
+            chunk["usage"] = usage(response);
+            self.finished = true;
+        }
+        format_event(None, &chunk.to_string())
+    }
+}
+
+/// Translates a Gemini response into an OpenAI chat completions response.
+/// `body` is the original chat completions request.
+pub async fn translate_response(body: &Bytes, resp: reqwest::Response) -> Response<Body> {
+    let status = resp.status();
+    if !status.is_success() {
+        // Gemini errors share the `error.message` shape of OpenAI errors
+        return get_response_stream(resp).await;
+    }
+
+    let chat = serde_json::from_slice::<Value>(body).unwrap_or_default();
+    let model = model_id(&chat);
+
+    if is_stream(&chat) {
+        let mut state = GeminiStream::new(model);
+        let stream = get_body_stream(resp)
+            .await
+            .into_data_stream()
+            .map(Some)
+            .chain(futures::stream::once(async { None }))
+            .map(move |chunk| match chunk {
+                Some(Ok(chunk)) => Ok(Bytes::from(state.push(&chunk).concat())),
+                Some(Err(e)) => Err(e),
+                None => Ok(Bytes::from(state.finish().concat())),
+            });
+        return (
+            status,
+            [
+                (header::CONTENT_TYPE, "text/event-stream"),
+                (header::CACHE_CONTROL, "no-cache"),
+            ],
+            Body::from_stream(stream),
+        )
+            .into_response();
+    }
+
+    match resp.json::<Value>().await {
+        Ok(response) => (
+            status,
+            [(header::CONTENT_TYPE, "application/json")],
+            to_chat_response(&response, &model).to_string(),
+        )
+            .into_response(),
+        Err(e) => {
+            tracing::warn!("Error parsing Gemini response: {}", e);
+            (StatusCode::BAD_GATEWAY, e.to_string()).into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(events: &[Bytes]) -> String {
+        events
+            .iter()
+            .map(|event| String::from_utf8_lossy(event))
+            .collect()
+    }
+
+    #[test]
+    fn ends_with_done_only_after_a_finish_reason() {
+        let part = r#"data: {"candidates":[{"content":{"parts":[{"text":"a"}]}}]}"#;
+        let mut stream = GeminiStream::new("gemini".to_owned());
+        stream.push(format!("{}\n\n", part).as_bytes());
+        assert!(!data(&stream.finish()).contains("[DONE]"));
+
+        let last =
+            r#"data: {"candidates":[{"content":{"parts":[{"text":"b"}]},"finishReason":"STOP"}]}"#;
+        let mut stream = GeminiStream::new("gemini".to_owned());
+        stream.push(format!("{}\n\n{}\n\n", part, last).as_bytes());
+        assert!(data(&stream.finish()).ends_with("data: [DONE]\n\n"));
+    }
+}
@@ -0,0 +1,3 @@
+pub mod anthropic;
+pub mod gemini;
+pub mod responses;
@@ -0,0 +1,640 @@
+use crate::utils::sse::{format_event, is_event_stream, SseParser};
+use axum::{
+    body::{Body, Bytes},
+    http::{header, Response, StatusCode},
+    response::IntoResponse,
+};
+use futures::StreamExt as _;
+use serde_json::{json, Value};
+
+fn text_of(content: &Value) -> String {
+    match content {
+        Value::String(text) => text.clone(),
+        Value::Array(parts) => parts
+            .iter()
+            .filter_map(|part| part["text"].as_str())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    }
+}
+
+fn content_parts(content: &Value) -> Value {
+    let Value::Array(parts) = content else {
+        return content.clone();
+    };
+    // plain text is the most widely supported content shape
+    if parts
+        .iter()
+        .all(|part| matches!(part["type"].as_str(), Some("input_text" | "output_text")))
+    {
+        return Value::String(text_of(content));
+    }
+    parts
+        .iter()
+        .filter_map(|part| match part["type"].as_str() {
+            Some("input_text" | "output_text") => {
+                Some(json!({ "type": "text", "text": part["text"] }))
+            }
+            Some("input_image") => {
+                let mut image_url = json!({ "url": part["image_url"] });
+                if !part["detail"].is_null() {
+                    image_url["detail"] = part["detail"].clone();
+                }
+                Some(json!({ "type": "image_url", "image_url": image_url }))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Translates a Responses API request into an OpenAI chat completions request.
+pub fn to_chat_body(request: &Value) -> Value {
+    let mut messages: Vec<Value> = vec![];
+    if let Some(instructions) = request["instructions"].as_str() {
+        messages.push(json!({ "role": "system", "content": instructions }));
+    }
+
+    match &request["input"] {
+        Value::String(input) => messages.push(json!({ "role": "user", "content": input })),
+        Value::Array(items) => {
+            for item in items {
+                match item["type"].as_str() {
+                    Some("function_call") => {
+                        let call = json!({
+                            "id": item["call_id"],
+                            "type": "function",
+                            "function": { "name": item["name"], "arguments": item["arguments"] },
+                        });
+                        // parallel calls belong to the same assistant message
+                        match messages.last_mut() {
+                            Some(last)
+                                if last["role"] == "assistant" && last["content"].is_null() =>
+                            {
+                                last["tool_calls"].as_array_mut().unwrap().push(call);
+                            }
+                            _ => messages.push(json!({
+                                "role": "assistant",
+                                "content": null,
+                                "tool_calls": [call],
+                            })),
+                        }
+                    }
+                    Some("function_call_output") => messages.push(json!({
+                        "role": "tool",
+                        "tool_call_id": item["call_id"],
+                        "content": text_of(&item["output"]),
+                    })),
+                    Some("message") | None => {
+                        let role = match item["role"].as_str() {
+                            Some("developer") => "system",
+                            Some(role) => role,
+                            None => "user",
+                        };
+                        messages.push(json!({
+                            "role": role,
+                            "content": content_parts(&item["content"]),
+                        }));
+                    }
+                    Some(other) => tracing::debug!("Skipping unsupported input item: {}", other),
+                }
+            }
+        }
+        _ => (),
+    }
+
+    let mut body = json!({ "model": request["model"], "messages": messages });
+    for (from, to) in [
+        ("max_output_tokens", "max_tokens"),
+        ("temperature", "temperature"),
+        ("top_p", "top_p"),
+        ("parallel_tool_calls", "parallel_tool_calls"),
+        ("user", "user"),
+    ] {
+        if !request[from].is_null() {
+            body[to] = request[from].clone();
+        }
+    }
+    if let Some(effort) = request["reasoning"]["effort"].as_str() {
+        body["reasoning_effort"] = json!(effort);
+    }
+    if request["stream"].as_bool().unwrap_or(false) {
+        body["stream"] = json!(true);
+        body["stream_options"] = json!({ "include_usage": true });
+    }
+
+    let format = &request["text"]["format"];
+    match format["type"].as_str() {
+        Some("json_object") => body["response_format"] = json!({ "type": "json_object" }),
+        Some("json_schema") => {
+            body["response_format"] = json!({
+                "type": "json_schema",
+                "json_schema": {
+                    "name": format["name"],
+                    "schema": format["schema"],
+                    "strict": format["strict"],
+                },
+            })
+        }
+        _ => (),
+    }
+
+    let tools = request["tools"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|tool| tool["type"] == "function")
+        .map(|tool| {
+            json!({
+                "type": "function",
+                "function": {
+                    "name": tool["name"],
+                    "description": tool["description"],
+                    "parameters": tool["parameters"],
+                },
+            })
+        })
+        .collect::<Vec<_>>();
+    if !tools.is_empty() {
+        body["tools"] = Value::Array(tools);
+    }
+    match &request["tool_choice"] {
+        Value::String(choice) => body["tool_choice"] = json!(choice),
+        Value::Object(choice) if choice.get("type") == Some(&json!("function")) => {
+            body["tool_choice"] =
+                json!({ "type": "function", "function": { "name": choice.get("name") } })
+        }
+        _ => (),
+    }
+    body
+}
+
+fn new_id(prefix: &str) -> String {
+    format!("{}_{}", prefix, chrono::Utc::now().timestamp_micros())
+}
+
+fn usage(chat: &Value) -> Value {
+    let tokens = |key: &str| chat["usage"][key].as_u64().unwrap_or(0);
+    json!({
+        "input_tokens": tokens("prompt_tokens"),
+        "output_tokens": tokens("completion_tokens"),
+        "total_tokens": tokens("total_tokens"),
+    })
+}
+
+/// Builds a response object around its output items.
+fn response_object(id: &str, request: &Value, status: &str, output: Vec<Value>) -> Value {
+    json!({
+        "id": id,
+        "object": "response",
+        "created_at": chrono::Utc::now().timestamp(),
+        "status": status,
+        "model": request["model"],
+        "instructions": request["instructions"],
+        "output": output,
+        "tools": request["tools"].as_array().cloned().unwrap_or_default(),
+        "tool_choice": request["tool_choice"].as_str().unwrap_or("auto"),
+        "parallel_tool_calls": request["parallel_tool_calls"].as_bool().unwrap_or(true),
+        "incomplete_details": match status {
+            "incomplete" => json!({ "reason": "max_output_tokens" }),
+            _ => Value::Null,
+        },
+        "error": null,
+        "usage": null,
+    })
+}
+
+fn status_of(finish_reason: Option<&str>) -> &'static str {
+    match finish_reason {
+        Some("length") => "incomplete",
+        _ => "completed",
+    }
+}
+
+fn message_item(id: &str, text: &str, status: &str) -> Value {
+    json!({
+        "type": "message",
+        "id": id,
+        "status": status,
+        "role": "assistant",
+        "content": [{ "type": "output_text", "text": text, "annotations": [] }],
+    })
+}
+
+fn function_call_item(
+    id: &str,
+    call_id: &Value,
+    name: &Value,
+    arguments: &str,
+    status: &str,
+) -> Value {
+    json!({
+        "type": "function_call",
+        "id": id,
+        "call_id": call_id,
+        "name": name,
+        "arguments": arguments,
+        "status": status,
+    })
+}
+
+/// Translates an OpenAI chat completion into a Responses API response.
+pub fn to_response(chat: &Value, request: &Value) -> Value {
+    let choice = &chat["choices"][0];
+    let message = &choice["message"];
+
+    let mut output = vec![];
+    if let Some(text) = message["content"].as_str().filter(|text| !text.is_empty()) {
+        output.push(message_item(&new_id("msg"), text, "completed"));
+    }
+    for (index, call) in message["tool_calls"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .enumerate()
+    {
+        output.push(function_call_item(
+            &format!("fc_{}", index),
+            &call["id"],
+            &call["function"]["name"],
+            call["function"]["arguments"].as_str().unwrap_or("{}"),
+            "completed",
+        ));
+    }
+
+    let status = status_of(choice["finish_reason"].as_str());
+    let mut response = response_object(&new_id("resp"), request, status, output);
+    if let Some(model) = chat["model"].as_str() {
+        response["model"] = json!(model);
+    }
+    response["usage"] = usage(chat);
+    response
+}
+
+#[derive(Debug)]
+enum OutputItem {
+    Message {
+        id: String,
+        text: String,
+    },
+    /// `index` is the index of the tool call in the chat stream
+    FunctionCall {
+        id: String,
+        index: u64,
+        call_id: Value,
+        name: Value,
+        arguments: String,
+    },
+}
+
+/// Translates an OpenAI chat completion stream into Responses API stream events.
+#[derive(Debug)]
+pub struct ResponsesStream {
+    request: Value,
+    id: String,
+    parser: SseParser,
+    sequence_number: u64,
+    started: bool,
+    finished: bool,
+    /// Completed output items
+    output: Vec<Value>,
+    current: Option<OutputItem>,
+    finish_reason: Option<String>,
+    usage: Value,
+}
+
+impl ResponsesStream {
+    pub fn new(request: Value) -> Self {
+        Self {
+            request,
+            id: new_id("resp"),
+            parser: SseParser::new(),
+            sequence_number: 0,
+            started: false,
+            finished: false,
+            output: vec![],
+            current: None,
+            finish_reason: None,
+            usage: Value::Null,
+        }
+    }
+
+    /// Feeds a chunk of the chat completion stream, returning the translated events.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<Bytes> {
+        let mut events = vec![];
+        for event in self.parser.push(chunk) {
+            if event.is_done() {
+                events.append(&mut self.finish());
+                continue;
+            }
+            match serde_json::from_str::<Value>(&event.data) {
+                Ok(chunk) => self.handle_chunk(&chunk, &mut events),
+                Err(e) => tracing::warn!("Error parsing stream chunk: {}", e),
+            }
+        }
+        events
+    }
+
+    /// Completes the response, if not completed yet.
+    pub fn finish(&mut self) -> Vec<Bytes> {
+        let mut events = vec![];
+        if self.finished {
+            return events;
+        }
+        self.finished = true;
+        self.start(&mut events);
+        self.close_item(&mut events);
+        let status = status_of(self.finish_reason.as_deref());
+        let mut response = response_object(&self.id, &self.request, status, self.output.clone());
+        response["usage"] = self.usage.clone();
+        let event_type = match status {
+            "incomplete" => "response.incomplete",
+            _ => "response.completed",
+        };
+        self.emit(event_type, json!({ "response": response }), &mut events);
+        events
+    }
+
+    fn emit(&mut self, event_type: &str, mut data: Value, events: &mut Vec<Bytes>) {
+        data["type"] = json!(event_type);
+        data["sequence_number"] = json!(self.sequence_number);
+        self.sequence_number += 1;
+        events.push(format_event(Some(event_type), &data.to_string()));
+    }
+
+    fn start(&mut self, events: &mut Vec<Bytes>) {
+        if self.started {
+            return;
+        }
+        self.started = true;
+        let response = response_object(&self.id, &self.request, "in_progress", vec![]);
+        self.emit("response.created", json!({ "response": response }), events);
+        self.emit(
+            "response.in_progress",
+            json!({ "response": response }),
+            events,
+        );
+    }
+
+    fn open_item(&mut self, item: OutputItem, events: &mut Vec<Bytes>) {
+        self.close_item(events);
+        let output_index = self.output.len();
+        match &item {
+            OutputItem::Message { id, .. } => {
+                let mut added = message_item(id, "", "in_progress");
+                added["content"] = json!([]);
+                self.emit(
+                    "response.output_item.added",
+                    json!({ "output_index": output_index, "item": added }),
+                    events,
+                );
+                self.emit(
+                    "response.content_part.added",
+                    json!({
+                        "item_id": id,
+                        "output_index": output_index,
+                        "content_index": 0,
+                        "part": { "type": "output_text", "text": "", "annotations": [] },
+                    }),
+                    events,
+                );
+            }
+            OutputItem::FunctionCall {
+                id, call_id, name, ..
+            } => self.emit(
+                "response.output_item.added",
+                json!({
+                    "output_index": output_index,
+                    "item": function_call_item(id, call_id, name, "", "in_progress"),
+                }),
+                events,
+            ),
+        }
+        self.current = Some(item);
+    }
+
+    fn close_item(&mut self, events: &mut Vec<Bytes>) {
+        let Some(item) = self.current.take() else {
+            return;
+        };
+        let output_index = self.output.len();
+        let done = match item {
+            OutputItem::Message { id, text } => {
+                self.emit(
+                    "response.output_text.done",
+                    json!({
+                        "item_id": id,
+                        "output_index": output_index,
+                        "content_index": 0,
+                        "text": text,
+                    }),
+                    events,
+                );
+                self.emit(
+                    "response.content_part.done",
+                    json!({
+                        "item_id": id,
+                        "output_index": output_index,
+                        "content_index": 0,
+                        "part": { "type": "output_text", "text": text, "annotations": [] },
+                    }),
+                    events,
+                );
+                message_item(&id, &text, "completed")
+            }
+            OutputItem::FunctionCall {
+                id,
+                call_id,
+                name,
+                arguments,
+                ..
+            } => {
+                self.emit(
+                    "response.function_call_arguments.done",
+                    json!({
+                        "item_id": id,
+                        "output_index": output_index,
+                        "arguments": arguments,
+                    }),
+                    events,
+                );
+                function_call_item(&id, &call_id, &name, &arguments, "completed")
+            }
+        };
+        self.emit(
+            "response.output_item.done",
+            json!({ "output_index": output_index, "item": done }),
+            events,
+        );
+        self.output.push(done);
+    }
+
+    /// Ends the response with `response.failed` instead of `response.completed`,
+    /// keeping the output items completed so far.
+    fn fail(&mut self, error: &Value, events: &mut Vec<Bytes>) {
+        self.finished = true;
+        self.start(events);
+        let mut response = response_object(&self.id, &self.request, "failed", self.output.clone());
+        response["usage"] = self.usage.clone();
+        response["error"] = json!({
+            "code": "server_error",
+            "message": error["message"].as_str().unwrap_or("Upstream stream failed"),
+        });
+        self.emit("response.failed", json!({ "response": response }), events);
+    }
+
+    fn handle_chunk(&mut self, chunk: &Value, events: &mut Vec<Bytes>) {
+        if self.finished {
+            return;
+        }
+        if chunk["error"].is_object() {
+            return self.fail(&chunk["error"], events);
+        }
+        self.start(events);
+        if chunk["usage"].is_object() {
+            self.usage = usage(chunk);
+        }
+        if let Some(model) = chunk["model"].as_str() {
+            self.request["model"] = json!(model);
+        }
+
+        let Some(choice) = chunk["choices"].get(0) else {
+            return;
+        };
+        let delta = &choice["delta"];
+
+        if let Some(text) = delta["content"].as_str().filter(|text| !text.is_empty()) {
+            if !matches!(self.current, Some(OutputItem::Message { .. })) {
+                let item = OutputItem::Message {
+                    id: new_id("msg"),
+                    text: String::new(),
+                };
+                self.open_item(item, events);
+            }
+            let output_index = self.output.len();
+            if let Some(OutputItem::Message { id, text: full }) = &mut self.current {
+                full.push_str(text);
+                let data = json!({
+                    "item_id": id,
+                    "output_index": output_index,
+                    "content_index": 0,
+                    "delta": text,
+                });
+                self.emit("response.output_text.delta", data, events);
+            }
+        }
+
+        for call in delta["tool_calls"].as_array().into_iter().flatten() {
+            let call_index = call["index"].as_u64().unwrap_or(0);
+            let is_current = matches!(
+                self.current,
+                Some(OutputItem::FunctionCall { index, .. }) if index == call_index
+            );
+            if !is_current {
+                let item = OutputItem::FunctionCall {
+                    id: format!("fc_{}", call_index),
+                    index: call_index,
+                    call_id: call["id"].clone(),
+                    name: call["function"]["name"].clone(),
+                    arguments: String::new(),
+                };
+                self.open_item(item, events);
+            }
+            let output_index = self.output.len();
+            if let (Some(OutputItem::FunctionCall { id, arguments, .. }), Some(delta)) = (
+                &mut self.current,
+                call["function"]["arguments"]
+                    .as_str()
+                    .filter(|arguments| !arguments.is_empty()),
+            ) {
+                arguments.push_str(delta);
+                let data = json!({ "item_id": id, "output_index": output_index, "delta": delta });
+                self.emit("response.function_call_arguments.delta", data, events);
+            }
+        }
+
+        if let Some(finish_reason) = choice["finish_reason"].as_str() {
+            self.finish_reason = Some(finish_reason.to_owned());
+        }
+    }
+}
+
+/// Translates an OpenAI chat completion response into a Responses API response.
+pub async fn translate_response(res: Response<Body>, request: &Value) -> Response<Body> {
+    let status = res.status();
+    let is_stream = is_event_stream(res.headers());
+
+    if !status.is_success() {
+        // errors share the same shape
+        return res;
+    }
+
+    if is_stream {
+        let mut state = ResponsesStream::new(request.clone());
+        let stream = res
+            .into_body()
+            .into_data_stream()
+            .map(Some)
+            .chain(futures::stream::once(async { None }))
+            .map(move |chunk| match chunk {
+                Some(Ok(chunk)) => Ok(Bytes::from(state.push(&chunk).concat())),
+                Some(Err(e)) => Err(e),
+                None => Ok(Bytes::from(state.finish().concat())),
+            });
+        return (
+            status,
+            [
+                (header::CONTENT_TYPE, "text/event-stream"),
+                (header::CACHE_CONTROL, "no-cache"),
+            ],
+            Body::from_stream(stream),
+        )
+            .into_response();
+    }
+
+    let body = match axum::body::to_bytes(res.into_body(), usize::MAX).await {
+        Ok(body) => body,
+        Err(e) => return (StatusCode::BAD_GATEWAY, e.to_string()).into_response(),
+    };
+    match serde_json::from_slice::<Value>(&body) {
+        Ok(chat) => (
+            status,
+            [(header::CONTENT_TYPE, "application/json")],
+            to_response(&chat, request).to_string(),
+        )
+            .into_response(),
+        Err(e) => {
+            tracing::warn!("Error parsing chat response: {}", e);
+            (StatusCode::BAD_GATEWAY, e.to_string()).into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(events: &[Bytes]) -> String {
+        events
+            .iter()
+            .map(|event| String::from_utf8_lossy(event))
+            .collect()
+    }
+
+    #[test]
+    fn ends_a_failed_stream_with_response_failed() {
+        let part = r#"data: {"id":"a","choices":[{"index":0,"delta":{"content":"a"}}]}"#;
+        let error = r#"data: {"error":{"message":"Upstream stream ended unexpectedly","type":"upstream_error","code":"stream_interrupted"}}"#;
+        let mut stream = ResponsesStream::new(json!({ "model": "gpt" }));
+        let mut events = stream.push(format!("{}\n\n{}\n\n", part, error).as_bytes());
+        events.append(&mut stream.finish());
+
+        let last = data(&events[events.len() - 1..]);
+        assert!(last.starts_with("event: response.failed\n"));
+        assert!(last.contains(r#""status":"failed""#));
+        assert!(last.contains(r#""message":"Upstream stream ended unexpectedly""#));
+        let events = data(&events);
+        assert!(events.contains("event: response.output_text.delta"));
+        assert!(!events.contains("response.completed"));
+    }
+}
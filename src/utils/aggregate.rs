@@ -0,0 +1,368 @@
+use crate::utils::sse::{is_event_stream, SseParser};
+use axum::{
+    body::{Body, Bytes},
+    http::{header, Response, StatusCode},
+    response::IntoResponse,
+};
+use futures::StreamExt as _;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Default)]
+struct ChoiceState {
+    role: Option<Value>,
+    content: Option<String>,
+    reasoning_content: Option<String>,
+    reasoning: Option<String>,
+    tool_calls: BTreeMap<u64, Value>,
+    logprobs: Vec<Value>,
+    finish_reason: Value,
+}
+
+/// Rebuilds a full chat completion out of the chunks of a chat completion stream.
+#[derive(Debug, Default)]
+pub struct ChatAggregator {
+    parser: SseParser,
+    id: Value,
+    created: Value,
+    model: Value,
+    system_fingerprint: Value,
+    choices: BTreeMap<u64, ChoiceState>,
+    usage: Value,
+    error: Option<Value>,
+    done: bool,
+}
+
+fn append(text: &mut Option<String>, delta: &Value) {
+    if let Some(delta) = delta.as_str() {
+        text.get_or_insert_with(String::new).push_str(delta);
+    }
+}
+
+impl ChatAggregator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds a chunk of the stream.
+    pub fn push(&mut self, chunk: &[u8]) {
+        for event in self.parser.push(chunk) {
+            self.handle_event(&event.data);
+        }
+    }
+
+    fn handle_event(&mut self, data: &str) {
+        if self.done {
+            return;
+        }
+        if data.trim() == "[DONE]" {
+            self.done = true;
+            return;
+        }
+        let chunk = match serde_json::from_str::<Value>(data) {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                tracing::warn!("Error parsing stream chunk: {}", e);
+                return;
+            }
+        };
+        if !chunk["error"].is_null() {
+            self.error = Some(chunk["error"].clone());
+            return;
+        }
+
+        for (field, key) in [
+            (&mut self.id, "id"),
+            (&mut self.created, "created"),
+            (&mut self.model, "model"),
+            (&mut self.system_fingerprint, "system_fingerprint"),
+        ] {
+            if field.is_null() {
+                *field = chunk[key].clone();
+            }
+        }
+        if !chunk["usage"].is_null() {
+            self.usage = chunk["usage"].clone();
+        }
+
+        for (i, choice) in chunk["choices"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .enumerate()
+        {
+            let index = choice["index"].as_u64().unwrap_or(i as u64);
+            let state = self.choices.entry(index).or_default();
+            let delta = &choice["delta"];
+
+            if !delta["role"].is_null() {
+                state.role = Some(delta["role"].clone());
+            }
+            append(&mut state.content, &delta["content"]);
+            append(&mut state.reasoning_content, &delta["reasoning_content"]);
+            append(&mut state.reasoning, &delta["reasoning"]);
+
+            for (position, call) in delta["tool_calls"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .enumerate()
+            {
+                let call_index = call["index"].as_u64().unwrap_or(position as u64);
+                let entry = state.tool_calls.entry(call_index).or_insert_with(|| {
+                    json!({
+                        "id": "",
+                        "type": "function",
+                        "function": { "name": "", "arguments": "" },
+                    })
+                });
+                if let Some(id) = call["id"].as_str() {
+                    entry["id"] = json!(id);
+                }
+                if let Some(kind) = call["type"].as_str() {
+                    entry["type"] = json!(kind);
+                }
+                for key in ["name", "arguments"] {
+                    if let Some(part) = call["function"][key].as_str() {
+                        let text = format!(
+                            "{}{}",
+                            entry["function"][key].as_str().unwrap_or_default(),
+                            part
+                        );
+                        entry["function"][key] = json!(text);
+                    }
+                }
+            }
+
+            if let Some(content) = choice["logprobs"]["content"].as_array() {
+                state.logprobs.extend(content.iter().cloned());
+            }
+            if !choice["finish_reason"].is_null() {
+                state.finish_reason = choice["finish_reason"].clone();
+            }
+        }
+    }
+
+    /// Whether the stream ended properly, with `[DONE]` or a finish reason for every choice.
+    pub fn is_complete(&self) -> bool {
+        self.done
+            || (!self.choices.is_empty()
+                && self
+                    .choices
+                    .values()
+                    .all(|choice| !choice.finish_reason.is_null()))
+    }
+
+    /// Whether the stream carried an error event.
+    pub fn has_error(&self) -> bool {
+        self.error.is_some()
+    }
+
+    /// Handles a trailing event not ended by a blank line, once the stream ended.
+    pub fn flush(&mut self) {
+        if let Some(event) = self.parser.finish() {
+            self.handle_event(&event.data);
+        }
+    }
+
+    /// Ends the stream, returning the full chat completion, or the error the stream ended with,
+    /// or an error if it was cut off before completing.
+    pub fn finish(mut self) -> Result<Value, Value> {
+        self.flush();
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        if !self.is_complete() {
+            return Err(json!({
+                "message": "Upstream stream ended unexpectedly",
+                "type": "upstream_error",
+                "code": "stream_interrupted",
+            }));
+        }
+
+        let choices = self
+            .choices
+            .into_iter()
+            .map(|(index, state)| {
+                let mut message = Map::new();
+                message.insert("role".to_owned(), state.role.unwrap_or(json!("assistant")));
+                message.insert("content".to_owned(), json!(state.content));
+                if let Some(reasoning_content) = state.reasoning_content {
+                    message.insert("reasoning_content".to_owned(), json!(reasoning_content));
+                }
+                if let Some(reasoning) = state.reasoning {
+                    message.insert("reasoning".to_owned(), json!(reasoning));
+                }
+                if !state.tool_calls.is_empty() {
+                    let tool_calls = state.tool_calls.into_values().collect::<Vec<_>>();
+                    message.insert("tool_calls".to_owned(), json!(tool_calls));
+                }
+                let logprobs = match state.logprobs.is_empty() {
+                    true => Value::Null,
+                    false => json!({ "content": state.logprobs }),
+                };
+                json!({
+                    "index": index,
+                    "message": message,
+                    "logprobs": logprobs,
+                    "finish_reason": state.finish_reason,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut response = json!({
+            "id": self.id,
+            "object": "chat.completion",
+            "created": self.created,
+            "model": self.model,
+            "choices": choices,
+        });
+        if !self.system_fingerprint.is_null() {
+            response["system_fingerprint"] = self.system_fingerprint;
+        }
+        if !self.usage.is_null() {
+            response["usage"] = self.usage;
+        }
+        Ok(response)
+    }
+}
+
+/// Turns a chat completion stream response into a full chat completion response,
+/// for upstreams that stream regardless of the request.
+/// Responses that are not streamed or not successful are returned as is.
+pub async fn aggregate_stream(res: Response<Body>, force: bool) -> Response<Body> {
+    if !res.status().is_success() || !(force || is_event_stream(res.headers())) {
+        return res;
+    }
+
+    let status = res.status();
+    let mut aggregator = ChatAggregator::new();
+    let mut stream = res.into_body().into_data_stream();
+    while let Some(chunk) = stream.next().await {
+        match chunk {
+            Ok(chunk) => aggregator.push(&chunk),
+            Err(e) => {
+                tracing::warn!("Error reading stream: {}", e);
+                return (StatusCode::BAD_GATEWAY, e.to_string()).into_response();
+            }
+        }
+    }
+
+    match aggregator.finish() {
+        Ok(response) => (
+            status,
+            [(header::CONTENT_TYPE, "application/json")],
+            Bytes::from(response.to_string()),
+        )
+            .into_response(),
+        Err(error) => {
+            tracing::warn!("Upstream stream failed: {}", error);
+            (
+                StatusCode::BAD_GATEWAY,
+                [(header::CONTENT_TYPE, "application/json")],
+                Bytes::from(json!({ "error": error }).to_string()),
+            )
+                .into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::sse::format_event;
+
+    fn push_chunks(aggregator: &mut ChatAggregator, chunks: &[Value]) {
+        for chunk in chunks {
+            aggregator.push(&format_event(None, &chunk.to_string()));
+        }
+    }
+
+    #[test]
+    fn concatenates_content_and_tool_call_arguments() {
+        let mut aggregator = ChatAggregator::new();
+        push_chunks(
+            &mut aggregator,
+            &[
+                json!({ "id": "x", "model": "m", "choices": [{ "index": 0, "delta": { "role": "assistant", "content": "He" } }] }),
+                json!({ "choices": [{ "index": 0, "delta": { "content": "llo" } }] }),
+                json!({ "choices": [{ "index": 0, "delta": { "tool_calls": [
+                    { "index": 0, "id": "call_1", "type": "function", "function": { "name": "get_", "arguments": "{\"ci" } },
+                ] } }] }),
+                json!({ "choices": [{ "index": 0, "delta": { "tool_calls": [
+                    { "index": 0, "function": { "name": "weather", "arguments": "ty\":\"Paris\"}" } },
+                    { "index": 1, "id": "call_2", "function": { "name": "now", "arguments": "{}" } },
+                ] } }] }),
+                json!({ "choices": [{ "index": 0, "delta": {}, "finish_reason": "tool_calls" }] }),
+            ],
+        );
+        assert!(aggregator.is_complete());
+
+        let chat = aggregator.finish().unwrap();
+        let message = &chat["choices"][0]["message"];
+        assert_eq!(chat["id"], "x");
+        assert_eq!(message["content"], "Hello");
+        assert_eq!(message["tool_calls"][0]["id"], "call_1");
+        assert_eq!(message["tool_calls"][0]["function"]["name"], "get_weather");
+        assert_eq!(
+            message["tool_calls"][0]["function"]["arguments"],
+            "{\"city\":\"Paris\"}"
+        );
+        assert_eq!(message["tool_calls"][1]["function"]["name"], "now");
+        assert_eq!(chat["choices"][0]["finish_reason"], "tool_calls");
+    }
+
+    #[test]
+    fn completes_on_trailing_done_without_blank_line() {
+        let mut aggregator = ChatAggregator::new();
+        aggregator
+            .push(b"data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"a\"}}]}\r\n\r\n");
+        assert!(!aggregator.is_complete());
+        aggregator.push(b"data: [DONE]");
+        let chat = aggregator.finish().unwrap();
+        assert_eq!(chat["choices"][0]["message"]["content"], "a");
+    }
+
+    #[test]
+    fn fails_a_stream_cut_off_mid_way() {
+        let mut aggregator = ChatAggregator::new();
+        push_chunks(
+            &mut aggregator,
+            &[json!({ "choices": [{ "index": 0, "delta": { "content": "a" } }] })],
+        );
+        assert_eq!(
+            aggregator.finish().unwrap_err()["code"],
+            "stream_interrupted"
+        );
+    }
+
+    #[tokio::test]
+    async fn aggregates_a_truncated_stream_into_a_bad_gateway() {
+        let res = Response::builder()
+            .header(header::CONTENT_TYPE, "text/event-stream")
+            .body(Body::from(
+                "data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"a\"}}]}\n\n",
+            ))
+            .unwrap();
+        let res = aggregate_stream(res, false).await;
+        assert_eq!(res.status(), StatusCode::BAD_GATEWAY);
+    }
+
+    #[test]
+    fn keeps_a_missing_finish_reason_null() {
+        let mut aggregator = ChatAggregator::new();
+        aggregator.push(b"data: {\"choices\":[{\"index\":0,\"delta\":{}}]}\n\ndata: [DONE]\n\n");
+        let chat = aggregator.finish().unwrap();
+        assert!(chat["choices"][0]["finish_reason"].is_null());
+    }
+
+    #[test]
+    fn returns_stream_error() {
+        let mut aggregator = ChatAggregator::new();
+        push_chunks(
+            &mut aggregator,
+            &[json!({ "error": { "message": "boom" } })],
+        );
+        assert_eq!(aggregator.finish().unwrap_err()["message"], "boom");
+    }
+}
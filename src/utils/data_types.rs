@@ -1,55 +1,508 @@
-use serde::{Deserialize, Serialize};
+//! OpenAI chat completion request, response and stream chunk types.
+//! Fields not modelled here are kept in `extra` and optional fields keep an explicit `null`,
+//! so bodies survive a parse and serialize round trip.
 
-#[allow(dead_code)]
-#[derive(Deserialize, Debug)]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{Map, Value};
+
+/// An optional field that tells an absent value from an explicit `null`.
+/// Use with `#[serde(default, skip_serializing_if = "Nullable::is_absent")]`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Nullable<T> {
+    #[default]
+    Absent,
+    Null,
+    Value(T),
+}
+
+impl<T> Nullable<T> {
+    pub fn is_absent(&self) -> bool {
+        matches!(self, Self::Absent)
+    }
+
+    pub fn value(&self) -> Option<&T> {
+        match self {
+            Self::Value(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl<T: Serialize> Serialize for Nullable<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Value(value) => value.serialize(serializer),
+            _ => serializer.serialize_none(),
+        }
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Nullable<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(match Option::<T>::deserialize(deserializer)? {
+            Some(value) => Self::Value(value),
+            None => Self::Null,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum MessageContent {
+    Text(String),
+    Parts(Vec<ContentPart>),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ImageUrl {
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub detail: Nullable<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct InputAudio {
+    pub data: String,
+    pub format: String,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// A part of a multimodal message, e.g. `text`, `image_url` or `input_audio`
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ContentPart {
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub text: Nullable<String>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub image_url: Nullable<ImageUrl>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub input_audio: Nullable<InputAudio>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct FunctionCall {
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub name: Nullable<String>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub arguments: Nullable<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// A tool call of an assistant message, or a part of one in a stream delta
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ToolCall {
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub index: Nullable<u32>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub id: Nullable<String>,
+    #[serde(rename = "type", default, skip_serializing_if = "Nullable::is_absent")]
+    pub kind: Nullable<String>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub function: Nullable<FunctionCall>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
 pub struct ChatMessage {
     pub role: String,
-    pub content: String,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub content: Nullable<MessageContent>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub name: Nullable<String>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub tool_calls: Nullable<Vec<ToolCall>>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub tool_call_id: Nullable<String>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub refusal: Nullable<String>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub reasoning_content: Nullable<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct FunctionDefinition {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub description: Nullable<String>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub parameters: Nullable<Value>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub strict: Nullable<bool>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Tool {
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub function: Nullable<FunctionDefinition>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct FunctionName {
+    pub name: String,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct NamedToolChoice {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub function: FunctionName,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// `none`, `auto`, `required`, or a specific function
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum ToolChoice {
+    Mode(String),
+    Named(NamedToolChoice),
+}
+
+/// `text`, `json_object`, or `json_schema` with its schema
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ResponseFormat {
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub json_schema: Nullable<Value>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
-#[allow(dead_code)]
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum Stop {
+    Single(String),
+    Multiple(Vec<String>),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct StreamOptions {
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub include_usage: Nullable<bool>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
 pub struct ChatBody {
     pub model: String,
     pub messages: Vec<ChatMessage>,
-    pub stream: Option<bool>,
-    pub max_tokens: Option<i32>,
-    pub temperature: Option<f32>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub stream: Nullable<bool>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub stream_options: Nullable<StreamOptions>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub max_tokens: Nullable<i32>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub max_completion_tokens: Nullable<i32>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub temperature: Nullable<f64>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub top_p: Nullable<f64>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub n: Nullable<u32>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub stop: Nullable<Stop>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub presence_penalty: Nullable<f64>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub frequency_penalty: Nullable<f64>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub logit_bias: Nullable<Map<String, Value>>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub logprobs: Nullable<bool>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub top_logprobs: Nullable<u32>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub seed: Nullable<i64>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub user: Nullable<String>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub tools: Nullable<Vec<Tool>>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub tool_choice: Nullable<ToolChoice>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub parallel_tool_calls: Nullable<bool>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub response_format: Nullable<ResponseFormat>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub reasoning_effort: Nullable<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
-#[allow(dead_code)]
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct TopLogprob {
+    pub token: String,
+    pub logprob: f64,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub bytes: Nullable<Vec<u8>>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct TokenLogprob {
+    pub token: String,
+    pub logprob: f64,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub bytes: Nullable<Vec<u8>>,
+    #[serde(default)]
+    pub top_logprobs: Vec<TopLogprob>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Logprobs {
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub content: Nullable<Vec<TokenLogprob>>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub refusal: Nullable<Vec<TokenLogprob>>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Usage {
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub prompt_tokens: Nullable<u64>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub completion_tokens: Nullable<u64>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub total_tokens: Nullable<u64>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub prompt_tokens_details: Nullable<Value>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub completion_tokens_details: Nullable<Value>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// The incremental message of a stream chunk
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
 pub struct Delta {
-    pub role: Option<String>,
-    pub content: String,
-    pub finish_reason: Option<String>,
-    pub match_stop: Option<i32>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub role: Nullable<String>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub content: Nullable<String>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub reasoning_content: Nullable<String>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub refusal: Nullable<String>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub tool_calls: Nullable<Vec<ToolCall>>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
-#[allow(dead_code)]
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
 pub struct Choice {
-    pub index: Option<i32>,
-    pub delta: Option<Delta>,
-    pub message: Option<Delta>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub index: Nullable<u32>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub message: Nullable<ChatMessage>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub delta: Nullable<Delta>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub logprobs: Nullable<Logprobs>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub finish_reason: Nullable<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
-#[allow(dead_code)]
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
 pub struct StreamChunk {
-    pub id: Option<String>,
-    pub object: Option<String>,
-    pub created: Option<i32>,
-    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub id: Nullable<String>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub object: Nullable<String>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub created: Nullable<i64>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub model: Nullable<String>,
+    #[serde(default)]
     pub choices: Vec<Choice>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub usage: Nullable<Usage>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub system_fingerprint: Nullable<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
-#[allow(dead_code)]
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
 pub struct ChatResponse {
-    pub id: Option<String>,
-    pub object: Option<String>,
-    pub created: Option<i32>,
-    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub id: Nullable<String>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub object: Nullable<String>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub created: Nullable<i64>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub model: Nullable<String>,
     pub choices: Vec<Choice>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub usage: Nullable<Usage>,
+    #[serde(default, skip_serializing_if = "Nullable::is_absent")]
+    pub system_fingerprint: Nullable<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn round_trip<T: Serialize + for<'de> Deserialize<'de>>(body: Value) -> T {
+        let typed: T = serde_json::from_value(body.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&typed).unwrap(), body);
+        typed
+    }
+
+    #[test]
+    fn keeps_unknown_fields() {
+        let chat: ChatBody = round_trip(json!({
+            "model": "m",
+            "messages": [{
+                "role": "user",
+                "content": [
+                    { "type": "text", "text": "hi", "cache_control": { "type": "ephemeral" } },
+                    { "type": "image_url", "image_url": { "url": "data:", "detail": "low", "x": 1 } },
+                ],
+                "vendor": true,
+            }],
+            "tools": [{
+                "type": "function",
+                "function": { "name": "f", "parameters": { "type": "object" }, "x": 2 },
+            }],
+            "tool_choice": { "type": "function", "function": { "name": "f" } },
+            "top_k": 40,
+        }));
+        assert_eq!(chat.extra["top_k"], 40);
+        assert_eq!(chat.messages[0].extra["vendor"], true);
+    }
+
+    #[test]
+    fn keeps_explicit_nulls() {
+        let chat: ChatBody = round_trip(json!({
+            "model": "m",
+            "messages": [
+                {
+                    "role": "assistant",
+                    "content": null,
+                    "tool_calls": [{
+                        "id": "call_1",
+                        "type": "function",
+                        "function": { "name": "f", "arguments": "{}" },
+                    }],
+                },
+                { "role": "tool", "content": "42", "tool_call_id": "call_1" },
+            ],
+            "temperature": null,
+            "stop": null,
+        }));
+        assert_eq!(chat.messages[0].content, Nullable::Null);
+        assert_eq!(chat.temperature, Nullable::Null);
+        assert_eq!(chat.stream, Nullable::Absent);
+    }
+
+    #[test]
+    fn keeps_absent_fields_absent() {
+        let chat: ChatBody = round_trip(json!({
+            "model": "m",
+            "messages": [{ "role": "user", "content": "hi" }],
+            "stream": true,
+        }));
+        assert_eq!(chat.stream.value(), Some(&true));
+        assert_eq!(
+            chat.messages[0].content,
+            Nullable::Value(MessageContent::Text("hi".to_owned()))
+        );
+    }
+
+    #[test]
+    fn round_trips_responses() {
+        let chat: ChatResponse = round_trip(json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "created": 1,
+            "model": "m",
+            "choices": [{
+                "index": 0,
+                "message": { "role": "assistant", "content": null, "refusal": null, "annotations": [] },
+                "logprobs": {
+                    "content": [{
+                        "token": "a",
+                        "logprob": -0.5,
+                        "bytes": [97],
+                        "top_logprobs": [{ "token": "a", "logprob": -0.5, "bytes": null }],
+                    }],
+                    "refusal": null,
+                },
+                "finish_reason": "stop",
+            }],
+            "usage": {
+                "prompt_tokens": 1,
+                "completion_tokens": 2,
+                "total_tokens": 3,
+                "prompt_tokens_details": { "cached_tokens": 0 },
+                "cost": 0.5,
+            },
+            "system_fingerprint": null,
+            "service_tier": "default",
+        }));
+        assert_eq!(
+            chat.choices[0].message.value().unwrap().content,
+            Nullable::Null
+        );
+        assert_eq!(chat.usage.value().unwrap().extra["cost"], 0.5);
+        assert_eq!(chat.extra["service_tier"], "default");
+    }
+
+    #[test]
+    fn round_trips_stream_chunks() {
+        let chunk: StreamChunk = round_trip(json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion.chunk",
+            "created": 1,
+            "model": "m",
+            "choices": [{
+                "index": 0,
+                "delta": {
+                    "content": "a",
+                    "tool_calls": [{ "index": 0, "function": { "arguments": "{" } }],
+                    "reasoning": "r",
+                },
+                "logprobs": null,
+                "finish_reason": null,
+            }],
+            "usage": null,
+        }));
+        let delta = chunk.choices[0].delta.value().unwrap();
+        assert_eq!(delta.extra["reasoning"], "r");
+        assert_eq!(chunk.choices[0].finish_reason, Nullable::Null);
+        assert_eq!(chunk.usage, Nullable::Null);
+    }
 }
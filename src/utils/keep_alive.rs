@@ -0,0 +1,181 @@
+use crate::utils::sse::{format_event, is_event_stream};
+use axum::{
+    body::{Body, BodyDataStream, Bytes},
+    http::{header, response::Parts, Response},
+};
+use futures::StreamExt as _;
+use serde_json::{json, Value};
+use std::{future::Future, pin::Pin, time::Duration};
+use tokio::time::Instant;
+
+/// Comment line sent to keep an idle event stream open
+const KEEP_ALIVE_COMMENT: &[u8] = b": keep-alive\n\n";
+
+enum KeepAlive<F> {
+    /// Waiting for the response, retries may still happen
+    Waiting(Pin<Box<F>>),
+    /// Waiting for the first chunk of the response stream
+    Starting(BodyDataStream),
+    Streaming(BodyDataStream),
+    Done,
+}
+
+/// Awaits the first chunk of a streaming request, starting a `200` event stream to the client
+/// if it takes longer than `interval`, kept open with `: keep-alive` comments until the
+/// first chunk arrives and the response stream is spliced in.
+/// The stream keeps the response headers if they arrived in time, e.g. `x-lift-cache`.
+/// A response that is not an event stream by then is sent as an `error` event.
+pub async fn keep_alive<F>(response: F, interval: Duration) -> Response<Body>
+where
+    F: Future<Output = Response<Body>> + Send + 'static,
+{
+    let deadline = Instant::now() + interval;
+    let mut response = Box::pin(response);
+    let res = match tokio::time::timeout_at(deadline, &mut response).await {
+        Ok(res) => res,
+        Err(_) => return start_stream(None, KeepAlive::Waiting(response), interval),
+    };
+    if !res.status().is_success() || !is_event_stream(res.headers()) {
+        return res;
+    }
+
+    let (parts, body) = res.into_parts();
+    let mut stream = body.into_data_stream();
+    match tokio::time::timeout_at(deadline, stream.next()).await {
+        Ok(first) => {
+            let stream = futures::stream::iter(first).chain(stream);
+            Response::from_parts(parts, Body::from_stream(stream))
+        }
+        Err(_) => start_stream(Some(parts), KeepAlive::<F>::Starting(stream), interval),
+    }
+}
+
+/// Starts the event stream to the client, with the response headers if there are any yet.
+fn start_stream<F>(parts: Option<Parts>, state: KeepAlive<F>, interval: Duration) -> Response<Body>
+where
+    F: Future<Output = Response<Body>> + Send + 'static,
+{
+    let stream = futures::stream::unfold(state, move |state| async move {
+        match state {
+            KeepAlive::Waiting(mut response) => {
+                tokio::select! {
+                    res = &mut response => {
+                        if !res.status().is_success() || !is_event_stream(res.headers()) {
+                            let event = error_event(res).await;
+                            return Some((Ok(event), KeepAlive::Done));
+                        }
+                        next_chunk(res.into_body().into_data_stream(), interval).await
+                    }
+                    _ = tokio::time::sleep(interval) => {
+                        let comment = Bytes::from_static(KEEP_ALIVE_COMMENT);
+                        Some((Ok(comment), KeepAlive::Waiting(response)))
+                    }
+                }
+            }
+            KeepAlive::Starting(stream) => next_chunk(stream, interval).await,
+            KeepAlive::Streaming(mut stream) => {
+                let chunk = stream.next().await?;
+                Some((chunk, KeepAlive::Streaming(stream)))
+            }
+            KeepAlive::Done => None,
+        }
+    });
+
+    let mut res = Response::new(Body::from_stream(stream));
+    if let Some(parts) = parts {
+        *res.headers_mut() = parts.headers;
+    }
+    let headers = res.headers_mut();
+    headers.remove(header::CONTENT_LENGTH);
+    headers.insert(
+        header::CONTENT_TYPE,
+        header::HeaderValue::from_static("text/event-stream"),
+    );
+    headers.insert(
+        header::CACHE_CONTROL,
+        header::HeaderValue::from_static("no-cache"),
+    );
+    res
+}
+
+/// Waits for the first chunk of the response stream, or sends a keep-alive comment
+/// if it does not arrive within `interval`.
+async fn next_chunk<F>(
+    mut stream: BodyDataStream,
+    interval: Duration,
+) -> Option<(Result<Bytes, axum::Error>, KeepAlive<F>)> {
+    match tokio::time::timeout(interval, stream.next()).await {
+        Ok(chunk) => Some((chunk?, KeepAlive::Streaming(stream))),
+        Err(_) => {
+            let comment = Bytes::from_static(KEEP_ALIVE_COMMENT);
+            Some((Ok(comment), KeepAlive::Starting(stream)))
+        }
+    }
+}
+
+/// Turns a response that cannot be spliced into the started stream into an `error` event,
+/// keeping the upstream error object if there is one.
+async fn error_event(res: Response<Body>) -> Bytes {
+    let status = res.status();
+    let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+        .await
+        .unwrap_or_default();
+    let error = match serde_json::from_slice::<Value>(&body) {
+        Ok(json) if json["error"].is_object() => json["error"].clone(),
+        _ => json!({
+            "message": String::from_utf8_lossy(&body),
+            "type": "upstream_error",
+            "code": status.as_str(),
+        }),
+    };
+    tracing::warn!("[KeepAlive] {} response after the stream started", status);
+    format_event(None, &json!({ "error": error }).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event_stream(body: Body) -> Response<Body> {
+        Response::builder()
+            .header(header::CONTENT_TYPE, "text/event-stream")
+            .header("x-lift-cache", "miss")
+            .body(body)
+            .unwrap()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn keeps_alive_until_the_first_chunk() {
+        let interval = Duration::from_secs(10);
+        let body = futures::stream::once(async {
+            tokio::time::sleep(Duration::from_secs(25)).await;
+            Ok::<_, axum::Error>(Bytes::from_static(b"data: [DONE]\n\n"))
+        });
+        let res = keep_alive(async { event_stream(Body::from_stream(body)) }, interval).await;
+        assert_eq!(res.headers()["x-lift-cache"], "miss");
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        // headers in time, no chunk by 10s, a keep-alive at 20s
+        assert_eq!(&body[..], b": keep-alive\n\ndata: [DONE]\n\n");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn sends_late_errors_as_events() {
+        let response = async {
+            tokio::time::sleep(Duration::from_secs(25)).await;
+            Response::builder()
+                .status(502)
+                .body(Body::from("bad gateway"))
+                .unwrap()
+        };
+        let res = keep_alive(response, Duration::from_secs(10)).await;
+        assert!(is_event_stream(res.headers()));
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.starts_with(": keep-alive\n\n"));
+        assert!(body.contains(r#""code":"502""#));
+    }
+}
@@ -1,2 +1,7 @@
+pub mod aggregate;
 pub mod data_types;
+pub mod keep_alive;
+pub mod pattern;
+pub mod sse;
 pub mod stream_body;
+pub mod stream_guard;
@@ -0,0 +1,322 @@
+use crate::utils::data_types::{
+    ChatResponse, Choice, Delta, MessageContent, Nullable, StreamChunk, ToolCall, Usage,
+};
+use axum::{
+    body::{Body, Bytes},
+    http::{header, HeaderMap, Response, StatusCode},
+    response::IntoResponse,
+};
+use serde_json::{json, Map, Value};
+
+/// A server-sent event.
+#[derive(Debug, Clone, Default)]
+pub struct SseEvent {
+    pub event: Option<String>,
+    pub data: String,
+}
+
+/// Incrementally parses server-sent events out of a byte stream.
+/// Chunks may end in the middle of a line or an event, which is kept until completed.
+/// Lines may end with LF, CRLF or CR, comment lines are ignored.
+#[derive(Debug, Default)]
+pub struct SseParser {
+    buffer: Vec<u8>,
+    event: SseEvent,
+    has_data: bool,
+    /// The last chunk ended with CR, so a leading LF belongs to the same line break
+    skip_lf: bool,
+    started: bool,
+}
+
+impl SseParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds a chunk, returning the events completed by it.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        let mut chunk = chunk;
+        if self.skip_lf && !chunk.is_empty() {
+            if chunk[0] == b'\n' {
+                chunk = &chunk[1..];
+            }
+            self.skip_lf = false;
+        }
+        self.buffer.extend_from_slice(chunk);
+        if !self.started && self.buffer.len() >= 3 {
+            if self.buffer.starts_with(b"\xEF\xBB\xBF") {
+                self.buffer.drain(..3);
+            }
+            self.started = true;
+        }
+
+        let mut events = vec![];
+        while let Some(pos) = self.buffer.iter().position(|b| *b == b'\n' || *b == b'\r') {
+            let mut end = pos + 1;
+            if self.buffer[pos] == b'\r' {
+                match self.buffer.get(pos + 1) {
+                    Some(b'\n') => end += 1,
+                    Some(_) => {}
+                    None => self.skip_lf = true,
+                }
+            }
+            let line = self.buffer.drain(..end).collect::<Vec<_>>();
+            let line = String::from_utf8_lossy(&line[..pos]).into_owned();
+            if let Some(event) = self.process_line(&line) {
+                events.push(event);
+            }
+        }
+        events
+    }
+
+    /// Ends the stream, returning the last event if the stream did not end with a blank line.
+    pub fn finish(&mut self) -> Option<SseEvent> {
+        let line = String::from_utf8_lossy(&std::mem::take(&mut self.buffer)).into_owned();
+        if !line.is_empty() {
+            self.process_line(&line);
+        }
+        self.process_line("")
+    }
+
+    fn process_line(&mut self, line: &str) -> Option<SseEvent> {
+        if line.is_empty() {
+            let event = std::mem::take(&mut self.event);
+            return std::mem::take(&mut self.has_data).then_some(event);
+        }
+        if line.starts_with(':') {
+            return None;
+        }
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+        match field {
+            "data" => {
+                if self.has_data {
+                    self.event.data.push('\n');
+                }
+                self.event.data.push_str(value);
+                self.has_data = true;
+            }
+            "event" => self.event.event = Some(value.to_owned()),
+            _ => {}
+        }
+        None
+    }
+}
+
+impl SseEvent {
+    /// Whether this is the `[DONE]` sentinel ending OpenAI streams.
+    pub fn is_done(&self) -> bool {
+        self.data.trim() == "[DONE]"
+    }
+}
+
+/// Formats a server-sent event.
+pub fn format_event(event: Option<&str>, data: &str) -> Bytes {
+    match event {
+        Some(event) => Bytes::from(format!("event: {}\ndata: {}\n\n", event, data)),
+        None => Bytes::from(format!("data: {}\n\n", data)),
+    }
+}
+
+/// Whether a response is a server-sent event stream.
+pub fn is_event_stream(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/event-stream"))
+}
+
+/// Size of the content chunks of a synthesized stream, in chars
+const SYNTHETIC_CHUNK_CHARS: usize = 64;
+
+/// Sets a part of a message text on a delta
+type SetPart = fn(&mut Delta, String);
+
+/// Converts a full chat completion into the chunks of an OpenAI chat completion stream:
+/// a role chunk, content chunks, a finish chunk per choice, a usage chunk, then `[DONE]`.
+pub fn chat_completion_to_events(chat: &ChatResponse) -> Vec<Bytes> {
+    let chunk = |choices: Vec<Choice>, usage: Nullable<Usage>| {
+        let chunk = StreamChunk {
+            id: chat.id.clone(),
+            object: Nullable::Value("chat.completion.chunk".to_owned()),
+            created: chat.created.clone(),
+            model: chat.model.clone(),
+            choices,
+            usage,
+            system_fingerprint: chat.system_fingerprint.clone(),
+            extra: Map::new(),
+        };
+        format_event(None, &json!(chunk).to_string())
+    };
+    let choice = |index: u32, delta: Delta, finish_reason: Nullable<String>| Choice {
+        index: Nullable::Value(index),
+        delta: Nullable::Value(delta),
+        finish_reason,
+        ..Default::default()
+    };
+    let delta = |index: u32, delta: Delta| {
+        chunk(vec![choice(index, delta, Nullable::Null)], Nullable::Absent)
+    };
+
+    let mut events = vec![];
+    for (i, full) in chat.choices.iter().enumerate() {
+        let index = full.index.value().copied().unwrap_or(i as u32);
+        let message = full.message.value().cloned().unwrap_or_default();
+
+        events.push(delta(
+            index,
+            Delta {
+                role: Nullable::Value("assistant".to_owned()),
+                content: Nullable::Value(String::new()),
+                ..Default::default()
+            },
+        ));
+        let content = match message.content.value() {
+            Some(MessageContent::Text(text)) => Some(text.as_str()),
+            _ => None,
+        };
+        let reasoning = message.extra.get("reasoning").and_then(Value::as_str);
+        // each text with how to set a part of it on a delta
+        let texts: [(Option<&str>, SetPart); 3] = [
+            (
+                message.reasoning_content.value().map(String::as_str),
+                |delta, part| delta.reasoning_content = Nullable::Value(part),
+            ),
+            (reasoning, |delta, part| {
+                delta.extra.insert("reasoning".to_owned(), json!(part));
+            }),
+            (content, |delta, part| delta.content = Nullable::Value(part)),
+        ];
+        for (text, set_part) in texts {
+            let text = text.unwrap_or_default().chars().collect::<Vec<_>>();
+            for part in text.chunks(SYNTHETIC_CHUNK_CHARS) {
+                let mut part_delta = Delta::default();
+                set_part(&mut part_delta, part.iter().collect());
+                events.push(delta(index, part_delta));
+            }
+        }
+        for (call_index, call) in message.tool_calls.value().into_iter().flatten().enumerate() {
+            let call = ToolCall {
+                index: Nullable::Value(call_index as u32),
+                ..call.clone()
+            };
+            let call_delta = Delta {
+                tool_calls: Nullable::Value(vec![call]),
+                ..Default::default()
+            };
+            events.push(delta(index, call_delta));
+        }
+        let finish_reason = match &full.finish_reason {
+            Nullable::Absent => Nullable::Null,
+            reason => reason.clone(),
+        };
+        events.push(chunk(
+            vec![choice(index, Delta::default(), finish_reason)],
+            Nullable::Absent,
+        ));
+    }
+    if let Nullable::Value(usage) = &chat.usage {
+        events.push(chunk(vec![], Nullable::Value(usage.clone())));
+    }
+    events.push(format_event(None, "[DONE]"));
+    events
+}
+
+/// Turns a full chat completion response into a chat completion stream response,
+/// for clients asking to stream from upstreams that only return full responses.
+/// Responses that are not a chat completion, e.g. a stream with a missing content type,
+/// are passed through as they are.
+pub async fn synthesize_stream(res: Response<Body>) -> Response<Body> {
+    let (parts, body) = res.into_parts();
+    let status = parts.status;
+    let body = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(e) => return (StatusCode::BAD_GATEWAY, e.to_string()).into_response(),
+    };
+    let chat = match serde_json::from_slice::<ChatResponse>(&body) {
+        Ok(chat) => chat,
+        Err(e) => {
+            tracing::warn!("Error parsing chat response, passing it through: {}", e);
+            return Response::from_parts(parts, Body::from(body));
+        }
+    };
+    (
+        status,
+        [
+            (header::CONTENT_TYPE, "text/event-stream"),
+            (header::CACHE_CONTROL, "no-cache"),
+        ],
+        Bytes::from(chat_completion_to_events(&chat).concat()),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(events: &[SseEvent]) -> Vec<&str> {
+        events.iter().map(|event| event.data.as_str()).collect()
+    }
+
+    #[test]
+    fn parses_frames_split_across_chunks() {
+        let mut parser = SseParser::new();
+        assert!(parser.push(b"data: {\"a\"").is_empty());
+        assert!(parser.push(b":1}\n").is_empty());
+        let events = parser.push(b"\ndata: [DO");
+        assert_eq!(data(&events), ["{\"a\":1}"]);
+        let events = parser.push(b"NE]\n\n");
+        assert_eq!(data(&events), ["[DONE]"]);
+        assert!(events[0].is_done());
+    }
+
+    #[test]
+    fn parses_crlf_and_cr_line_endings() {
+        let mut parser = SseParser::new();
+        let events = parser.push(b"event: message\r\ndata: a\r\n\r\ndata: b\r");
+        assert_eq!(data(&events), ["a"]);
+        assert_eq!(events[0].event.as_deref(), Some("message"));
+        // the LF of a CRLF split across chunks is not a second line break
+        let events = parser.push(b"\n\r\n");
+        assert_eq!(data(&events), ["b"]);
+    }
+
+    #[test]
+    fn skips_bom_and_comments_and_joins_data_lines() {
+        let mut parser = SseParser::new();
+        let events = parser.push(b"\xEF\xBB\xBF: keep-alive\n\ndata: a\ndata:b\n\n");
+        assert_eq!(data(&events), ["a\nb"]);
+    }
+
+    #[test]
+    fn finishes_trailing_event_without_blank_line() {
+        let mut parser = SseParser::new();
+        assert_eq!(parser.push(b"data: a\n\ndata: [DONE]").len(), 1);
+        let event = parser.finish().expect("trailing event");
+        assert!(event.is_done());
+        assert!(parser.finish().is_none());
+    }
+
+    #[test]
+    fn round_trips_synthesized_events() {
+        let chat = json!({
+            "id": "x",
+            "created": 1,
+            "model": "m",
+            "choices": [{
+                "index": 0,
+                "message": { "role": "assistant", "content": "hello" },
+                "finish_reason": "stop",
+            }],
+        });
+        let mut parser = SseParser::new();
+        let chat = serde_json::from_value(chat).unwrap();
+        let events = parser.push(&chat_completion_to_events(&chat).concat());
+        assert!(events.last().is_some_and(SseEvent::is_done));
+        let chunk = serde_json::from_str::<Value>(&events[1].data).unwrap();
+        assert_eq!(chunk["choices"][0]["delta"]["content"], "hello");
+    }
+}
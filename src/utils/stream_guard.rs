@@ -0,0 +1,146 @@
+use crate::utils::{
+    aggregate::ChatAggregator,
+    sse::{format_event, is_event_stream},
+};
+use axum::{
+    body::{Body, Bytes},
+    http::Response,
+};
+use futures::StreamExt as _;
+use serde_json::json;
+
+/// How a chat completion stream ended without completing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interruption {
+    /// The body failed, the failure is already recorded upstream
+    Dropped,
+    /// The body ended without `[DONE]` or a finish reason for every choice
+    Truncated,
+}
+
+/// Watches a chat completion stream for an end without `[DONE]` or a finish reason.
+#[derive(Debug, Default)]
+struct StreamGuard {
+    aggregator: ChatAggregator,
+    ended: bool,
+}
+
+impl StreamGuard {
+    fn push(&mut self, chunk: &[u8]) {
+        self.aggregator.push(chunk);
+    }
+
+    /// Ends the stream, returning how and why if it did not complete.
+    fn finish(&mut self, dropped: Option<String>) -> Option<(Interruption, String)> {
+        if std::mem::replace(&mut self.ended, true) {
+            return None;
+        }
+        self.aggregator.flush();
+        match dropped {
+            Some(e) => Some((
+                Interruption::Dropped,
+                format!("Upstream stream failed: {}", e),
+            )),
+            // a stream carrying its own error event needs no other
+            None if self.aggregator.is_complete() || self.aggregator.has_error() => None,
+            None => Some((
+                Interruption::Truncated,
+                "Upstream stream ended unexpectedly".to_owned(),
+            )),
+        }
+    }
+}
+
+/// Formats the OpenAI-style error event ending an interrupted stream.
+fn error_event(message: &str) -> Bytes {
+    let error = json!({
+        "error": {
+            "message": message,
+            "type": "upstream_error",
+            "code": "stream_interrupted",
+        }
+    });
+    format_event(None, &error.to_string())
+}
+
+/// Ends a chat completion stream that is cut off with an OpenAI-style `error` event,
+/// instead of a failed body or a silently truncated one.
+/// `on_interrupt` is called with how and why the stream was cut off.
+/// Responses that are not streamed or not successful are returned as is.
+pub fn guard_stream<F>(res: Response<Body>, on_interrupt: F) -> Response<Body>
+where
+    F: FnOnce(Interruption, &str) + Send + 'static,
+{
+    if !res.status().is_success() || !is_event_stream(res.headers()) {
+        return res;
+    }
+
+    let (parts, body) = res.into_parts();
+    let mut guard = StreamGuard::default();
+    let mut on_interrupt = Some(on_interrupt);
+    let stream = body
+        .into_data_stream()
+        .map(Some)
+        .chain(futures::stream::once(async { None }))
+        .filter_map(move |chunk| {
+            let chunk = match chunk {
+                _ if guard.ended => None,
+                Some(Ok(chunk)) => {
+                    guard.push(&chunk);
+                    Some(chunk)
+                }
+                chunk => {
+                    let dropped = chunk.and_then(Result::err).map(|e| e.to_string());
+                    guard.finish(dropped).map(|(interruption, message)| {
+                        if let Some(on_interrupt) = on_interrupt.take() {
+                            on_interrupt(interruption, &message);
+                        }
+                        error_event(&message)
+                    })
+                }
+            };
+            async move { chunk.map(Ok::<_, axum::Error>) }
+        });
+    Response::from_parts(parts, Body::from_stream(stream))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::header;
+    use std::sync::{Arc, Mutex};
+
+    async fn guarded(body: &'static str) -> (String, Option<Interruption>) {
+        let res = Response::builder()
+            .header(header::CONTENT_TYPE, "text/event-stream")
+            .body(Body::from(body))
+            .unwrap();
+        let interrupted = Arc::new(Mutex::new(None));
+        let seen = interrupted.clone();
+        let res = guard_stream(res, move |interruption, _| {
+            *seen.lock().unwrap() = Some(interruption);
+        });
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let interruption = *interrupted.lock().unwrap();
+        (String::from_utf8(body.to_vec()).unwrap(), interruption)
+    }
+
+    #[tokio::test]
+    async fn passes_complete_streams_through() {
+        let stream = "data: {\"choices\":[{\"index\":0,\"finish_reason\":\"stop\"}]}";
+        assert_eq!(guarded(stream).await, (stream.to_owned(), None));
+        let stream = "data: {\"choices\":[{\"index\":0,\"delta\":{}}]}\n\ndata: [DONE]\n\n";
+        assert_eq!(guarded(stream).await, (stream.to_owned(), None));
+    }
+
+    #[tokio::test]
+    async fn ends_truncated_streams_with_an_error_event() {
+        let stream = "data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"a\"}}]}\n\n";
+        let (body, interruption) = guarded(stream).await;
+        assert_eq!(interruption, Some(Interruption::Truncated));
+        assert!(body.starts_with(stream));
+        assert!(body.contains("stream_interrupted"));
+    }
+}